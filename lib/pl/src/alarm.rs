@@ -0,0 +1,271 @@
+use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum AlarmLevel {
+    HiHi,
+    Hi,
+    Lo,
+    LoLo,
+    RateOfChange,
+    Equals,
+}
+
+impl fmt::Display for AlarmLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                AlarmLevel::HiHi => "hihi",
+                AlarmLevel::Hi => "hi",
+                AlarmLevel::Lo => "lo",
+                AlarmLevel::LoLo => "lolo",
+                AlarmLevel::RateOfChange => "roc",
+                AlarmLevel::Equals => "eq",
+            }
+        )
+    }
+}
+
+fn de_alarm_level<'de, D>(deserializer: D) -> serde::export::Result<AlarmLevel, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let level = String::deserialize(deserializer).unwrap();
+    return Ok(match level.to_lowercase().as_str() {
+        "hihi" | "hi-hi" => AlarmLevel::HiHi,
+        "hi" => AlarmLevel::Hi,
+        "lo" => AlarmLevel::Lo,
+        "lolo" | "lo-lo" => AlarmLevel::LoLo,
+        "roc" | "rate-of-change" => AlarmLevel::RateOfChange,
+        "eq" | "equals" => AlarmLevel::Equals,
+        _ => unimplemented!("alarm level {}", level),
+    });
+}
+
+fn get_default_hysteresis() -> f64 {
+    0.0
+}
+
+fn get_default_delay() -> f64 {
+    0.0
+}
+
+pub fn empty_alarm_list() -> AlarmList {
+    return vec![];
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Alarm {
+    #[serde(deserialize_with = "de_alarm_level")]
+    level: AlarmLevel,
+    // limit, max change per second for roc
+    value: f64,
+    #[serde(default = "get_default_hysteresis")]
+    hysteresis: f64,
+    // seconds the condition must hold before the alarm is set
+    #[serde(alias = "on-delay", default = "get_default_delay")]
+    on_delay: f64,
+    // seconds the condition must be gone before the alarm is cleared
+    #[serde(alias = "off-delay", default = "get_default_delay")]
+    off_delay: f64,
+}
+
+pub type AlarmList = Vec<Alarm>;
+
+// alarm event value
+pub struct AlarmState {
+    pub level: AlarmLevel,
+    pub active: bool,
+}
+
+impl fmt::Display for AlarmState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.level,
+            match self.active {
+                true => "on",
+                false => "off",
+            }
+        )
+    }
+}
+
+impl Serialize for AlarmState {
+    fn serialize<S>(&self, serializer: S) -> serde::export::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("level", &self.level.to_string())?;
+        map.serialize_entry("active", &self.active)?;
+        return map.end();
+    }
+}
+
+#[derive(Debug)]
+struct AlarmInfo {
+    active: bool,
+    pending_since: Option<Instant>,
+    prev: Option<(f64, Instant)>,
+}
+
+thread_local!(static ALARM_INFO: RefCell<HashMap<(u64, usize), AlarmInfo>>
+    = RefCell::new(HashMap::new()));
+
+impl Alarm {
+    fn condition(
+        &self,
+        value: f64,
+        active: bool,
+        prev: Option<(f64, Instant)>,
+        t: Instant,
+    ) -> bool {
+        // hysteresis keeps the alarm active until the value returns back
+        let hyst = match active {
+            true => self.hysteresis,
+            false => 0.0,
+        };
+        return match self.level {
+            AlarmLevel::HiHi | AlarmLevel::Hi => value > self.value - hyst,
+            AlarmLevel::Lo | AlarmLevel::LoLo => value < self.value + hyst,
+            AlarmLevel::Equals => value == self.value,
+            AlarmLevel::RateOfChange => match prev {
+                Some((prev_value, prev_t)) => {
+                    let t_delta = t.duration_since(prev_t).as_secs_f64();
+                    if t_delta > 0.0 {
+                        ((value - prev_value) / t_delta).abs() > self.value - hyst
+                    } else {
+                        active
+                    }
+                }
+                None => false,
+            },
+        };
+    }
+}
+
+// returns the list of alarm state changes
+pub fn process_alarms(alarms: &AlarmList, id_hash: u64, value: f64, t: Instant) -> Vec<AlarmState> {
+    let mut result = Vec::new();
+    ALARM_INFO.with(|alarm_info_cell| {
+        let mut ainfo = alarm_info_cell.borrow_mut();
+        for (i, alarm) in alarms.iter().enumerate() {
+            let info = ainfo.entry((id_hash, i)).or_insert(AlarmInfo {
+                active: false,
+                pending_since: None,
+                prev: None,
+            });
+            let cond = alarm.condition(value, info.active, info.prev, t);
+            info.prev = Some((value, t));
+            if cond == info.active {
+                info.pending_since = None;
+                continue;
+            }
+            let since = *info.pending_since.get_or_insert(t);
+            let delay = match cond {
+                true => alarm.on_delay,
+                false => alarm.off_delay,
+            };
+            if t.duration_since(since).as_secs_f64() >= delay {
+                info.active = cond;
+                info.pending_since = None;
+                result.push(AlarmState {
+                    level: alarm.level,
+                    active: cond,
+                });
+            }
+        }
+    });
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn alarms(yaml: &str) -> AlarmList {
+        return serde_yaml::from_str(yaml).unwrap();
+    }
+
+    // processes the values at the given seconds, returns the state changes
+    fn run(alarms: &AlarmList, id_hash: u64, values: &[(f64, f64)]) -> Vec<String> {
+        let t = Instant::now();
+        let mut result = Vec::new();
+        for (secs, value) in values {
+            for state in process_alarms(alarms, id_hash, *value, t + Duration::from_secs_f64(*secs))
+            {
+                result.push(format!("{} {}", secs, state));
+            }
+        }
+        return result;
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let a = alarms("[{level: hi, value: 10, hysteresis: 2}]");
+        let states = run(
+            &a,
+            1,
+            &[(0.0, 9.0), (1.0, 11.0), (2.0, 9.0), (3.0, 8.5), (4.0, 7.0)],
+        );
+        // 9 and 8.5 are inside the deadband
+        assert_eq!(states, vec!["1 hi on", "4 hi off"]);
+        let a = alarms("[{level: lolo, value: 0, hysteresis: 1}]");
+        let states = run(&a, 2, &[(0.0, -1.0), (1.0, 0.5), (2.0, 1.5)]);
+        assert_eq!(states, vec!["0 lolo on", "2 lolo off"]);
+    }
+
+    #[test]
+    fn test_delays() {
+        let a = alarms("[{level: hi, value: 10, on-delay: 2, off-delay: 1}]");
+        let states = run(
+            &a,
+            3,
+            &[
+                (0.0, 11.0),
+                (1.0, 11.0),
+                // the condition is gone before the delay expired
+                (1.5, 5.0),
+                (2.0, 11.0),
+                (3.0, 11.0),
+                (4.0, 11.0),
+                (5.0, 5.0),
+                (5.5, 5.0),
+                (6.0, 5.0),
+            ],
+        );
+        assert_eq!(states, vec!["4 hi on", "6 hi off"]);
+    }
+
+    #[test]
+    fn test_roc() {
+        let a = alarms("[{level: roc, value: 10}]");
+        let states = run(
+            &a,
+            4,
+            &[
+                (0.0, 0.0),
+                (1.0, 5.0),
+                (2.0, 20.0),
+                (3.0, 22.0),
+                (3.5, 12.0),
+            ],
+        );
+        assert_eq!(states, vec!["2 roc on", "3 roc off", "3.5 roc on"]);
+    }
+
+    #[test]
+    fn test_eq() {
+        let a = alarms("[{level: eq, value: 3}, {level: hi, value: 3}]");
+        let states = run(&a, 5, &[(0.0, 3.0), (1.0, 4.0), (2.0, 2.0)]);
+        assert_eq!(states, vec!["0 eq on", "1 eq off", "1 hi on", "2 hi off"]);
+    }
+}
@@ -1,3 +1,5 @@
+#[path = "alarm.rs"]
+pub mod alarm;
 #[path = "datatypes.rs"]
 pub mod datatypes;
 #[path = "tools.rs"]
@@ -83,10 +85,29 @@ impl EventTimer {
 }
 
 use std::cell::RefCell;
-thread_local!(static EVENT_CACHE: RefCell<HashMap<u64, String>> = RefCell::new(HashMap::new()));
+
+struct CachedEvent {
+    value: String,
+    // time of the last value change
+    changed: Instant,
+    // time of the last stale event
+    stale_reported: Option<Instant>,
+}
+
+thread_local!(static EVENT_CACHE: RefCell<HashMap<u64, CachedEvent>> = RefCell::new(HashMap::new()));
+
+// receives id, value and quality of every outputted event
+pub type EventListener = Box<dyn Fn(&str, &str, datatypes::Quality) + Send + Sync>;
 
 lazy_static! {
     static ref EVENT_TIMER: RwLock<EventTimer> = RwLock::new(EventTimer::new());
+    static ref EVENT_LISTENERS: RwLock<Vec<EventListener>> = RwLock::new(Vec::new());
+}
+
+// the listener is called for all events, written to the output (e.g. to
+// expose them via the Modbus server)
+pub fn add_event_listener(listener: EventListener) {
+    EVENT_LISTENERS.write().unwrap().push(listener);
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -119,14 +140,28 @@ impl Core {
         return datatypes::EventTime::new(self.time_format);
     }
 
+    pub fn timestamp_value(self, secs: f64) -> datatypes::Value {
+        return datatypes::timestamp_value(secs, self.time_format);
+    }
+
     pub fn create_event<'a, T: ToString + transform::Transform>(
         self,
         id: &'a String,
         value: T,
         transform: &'a datatypes::EventTransformList,
+        alarms: &'a alarm::AlarmList,
+        checks: &'a datatypes::ValueChecks,
         t: &'a datatypes::EventTime,
     ) -> Event<'a, T> {
-        return Event::new(id, value, transform, t, self.event_output_flags);
+        return Event::new(
+            id,
+            value,
+            transform,
+            alarms,
+            checks,
+            t,
+            self.event_output_flags,
+        );
     }
 
     pub fn output<T: serde::Serialize + std::fmt::Display + transform::Transform>(
@@ -136,6 +171,22 @@ impl Core {
         self._output(event, 0);
     }
 
+    // outputs null event with the quality set (e.g. for failed pulls),
+    // transformations and alarms are skipped
+    pub fn output_quality<T: ToString + transform::Transform>(
+        self,
+        event: &Event<T>,
+        quality: datatypes::Quality,
+    ) {
+        self.emit(&event.invalidate(quality));
+    }
+
+    // outputs the event as-is, bypassing the cache (e.g. write command
+    // acknowledgements)
+    pub fn output_direct<T: serde::Serialize + std::fmt::Display>(self, event: &Event<T>) {
+        self.write(event);
+    }
+
     pub fn since_event(self) -> Option<Duration> {
         match self.event_timeout {
             Some(_) => Some(EVENT_TIMER.read().unwrap().since_event()),
@@ -155,6 +206,16 @@ impl Core {
         event: &Event<T>,
         ti: usize,
     ) {
+        // sentinel values are checked before transformations, NaN / Inf on
+        // every stage
+        if (ti == 0 && event.checks.contains(&event.value))
+            || !datatypes::is_finite_value(&event.value)
+        {
+            if !event.checks.skip() {
+                self.emit(&event.invalidate(datatypes::Quality::Bad));
+            }
+            return;
+        }
         // transform if required
         if event.transform_list.len() > ti {
             match event.transform_at(ti) {
@@ -163,24 +224,125 @@ impl Core {
             }
             return;
         }
-        //
-        let val = event.value.to_string();
-        EVENT_CACHE.with(|event_cache_cell| {
+        if datatypes::is_uptime_id(event.id_hash) {
+            if let Some(value) = datatypes::get_num_value(&event.value) {
+                transform::set_uptime(event.id_hash, value);
+            }
+        }
+        if event.checks.in_range(&event.value) {
+            self.emit(event);
+        } else {
+            self.emit(&Event {
+                id: event.id,
+                id_hash: event.id_hash,
+                value: &event.value,
+                t: event.t,
+                transform_list: event.transform_list,
+                alarms: event.alarms,
+                checks: event.checks,
+                unit: event.unit,
+                quality: datatypes::Quality::OutOfRange,
+                output_flags: event.output_flags,
+            });
+        }
+        if event.alarms.is_empty() {
+            return;
+        }
+        // alarms are not evaluated for non-numeric strings
+        if let Some(value) = datatypes::get_num_value(&event.value) {
+            let states =
+                alarm::process_alarms(event.alarms, event.id_hash, value, event.t.monotonic());
+            if !states.is_empty() {
+                let alarm_id = format!("{}.alarm", event.id);
+                // alarm state changes are always written, bypassing the cache
+                for state in states {
+                    self.write(&Event {
+                        id: &alarm_id,
+                        id_hash: datatypes::calculate_hash(&alarm_id),
+                        value: state,
+                        t: event.t,
+                        transform_list: event.transform_list,
+                        alarms: event.alarms,
+                        checks: event.checks,
+                        unit: None,
+                        quality: datatypes::Quality::Good,
+                        output_flags: event.output_flags,
+                    });
+                }
+            }
+        }
+    }
+
+    fn emit<T: serde::Serialize + std::fmt::Display>(self, event: &Event<T>) {
+        // quality changes are emitted even if the value is the same
+        let val = match event.quality {
+            datatypes::Quality::Good => event.value.to_string(),
+            q => format!("{} {}", event.value, q),
+        };
+        let t = event.t.monotonic();
+        let stale = EVENT_CACHE.with(|event_cache_cell| {
             let mut cache = event_cache_cell.borrow_mut();
-            let prev = cache.get(&event.id_hash);
-            if !prev.is_some() || *prev.unwrap() != val {
-                cache.insert(event.id_hash, val);
-                if self.event_timeout.is_some() {
-                    EVENT_TIMER.write().unwrap().trigger();
+            match cache.get_mut(&event.id_hash) {
+                Some(cached) if cached.value == val => {
+                    if !event.quality.is_null()
+                        && event
+                            .checks
+                            .is_stale(cached.changed, cached.stale_reported, t)
+                    {
+                        cached.stale_reported = Some(t);
+                        return true;
+                    }
+                }
+                _ => {
+                    cache.insert(
+                        event.id_hash,
+                        CachedEvent {
+                            value: val,
+                            changed: t,
+                            stale_reported: None,
+                        },
+                    );
+                    self.write(event);
                 }
-                match self.tp {
-                    OutputType::Stdout => output_stdout(event),
-                    OutputType::StdoutCsv => output_stdout_csv(event),
-                    OutputType::StdoutNdJson => output_stdout_ndjson(event),
-                    OutputType::StdoutEvaDatapuller => output_eva_datapuller(event),
-                };
             }
+            return false;
         });
+        // stale events are always written, bypassing the cache
+        if stale {
+            self.write(&Event {
+                id: event.id,
+                id_hash: event.id_hash,
+                value: &event.value,
+                t: event.t,
+                transform_list: event.transform_list,
+                alarms: event.alarms,
+                checks: event.checks,
+                unit: event.unit,
+                quality: datatypes::Quality::Stale,
+                output_flags: event.output_flags,
+            });
+        }
+    }
+
+    fn write<T: serde::Serialize + std::fmt::Display>(self, event: &Event<T>) {
+        if self.event_timeout.is_some() {
+            EVENT_TIMER.write().unwrap().trigger();
+        }
+        {
+            let listeners = EVENT_LISTENERS.read().unwrap();
+            if !listeners.is_empty() {
+                let value = event.value.to_string();
+                for listener in listeners.iter() {
+                    listener(event.id, &value, event.quality);
+                }
+            }
+        }
+        match self.tp {
+            OutputType::Stdout => output_stdout(event),
+            OutputType::StdoutCsv => output_stdout_csv(event),
+            OutputType::StdoutNdJson => output_stdout_ndjson(event),
+            OutputType::StdoutEvaDatapuller => output_eva_datapuller(event),
+        };
     }
 
     pub fn clear_event_cache(self) {
@@ -196,12 +358,15 @@ pub fn output_stdout<T: ToString>(event: &Event<T>) {
     if t != "" {
         t = t + " "
     }
-    let s = format!(
+    let mut s = format!(
         "{}{} {}",
         t.white().dimmed(),
         event.id.blue().bold(),
         event.value.to_string().yellow()
     );
+    if event.quality != datatypes::Quality::Good {
+        s = format!("{} {}", s, event.quality.to_string().red());
+    }
     oprint(s);
 }
 
@@ -210,7 +375,10 @@ pub fn output_stdout_csv<T: std::fmt::Display>(event: &Event<T>) {
     if t != "" {
         t = t + ";"
     }
-    let s = format!("{}{};{}", t, event.id, event.value);
+    let mut s = format!("{}{};{}", t, event.id, event.value);
+    if event.quality != datatypes::Quality::Good {
+        s = format!("{};{}", s, event.quality);
+    }
     oprint(s);
 }
 
@@ -219,7 +387,16 @@ pub fn output_stdout_ndjson<T: serde::Serialize + std::fmt::Display>(event: &Eve
 }
 
 pub fn output_eva_datapuller<T: std::fmt::Display>(event: &Event<T>) {
-    if event.id.ends_with(".value") {
+    // items without data are set to the error status
+    if event.quality.is_null() {
+        let oid = match event.id.rfind('.') {
+            Some(pos) if event.id.ends_with(".value") || event.id.ends_with(".status") => {
+                &event.id[..pos]
+            }
+            _ => event.id,
+        };
+        oprint(format!("{} u -1", oid));
+    } else if event.id.ends_with(".value") {
         oprint(format!(
             "{} u None {}",
             &event.id[..event.id.len() - 6],
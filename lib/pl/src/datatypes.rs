@@ -2,13 +2,21 @@ const ERROR_SYSTEM_TIME: &str = "invalid system time";
 
 use chrono::{Local, TimeZone};
 use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
+use std::fs;
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::RwLock;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use transform;
 
+use crate::alarm::AlarmList;
+use crate::tools::eprint;
+
 pub fn calculate_hash<T: Hash>(t: &T) -> u64 {
     let mut s = DefaultHasher::new();
     t.hash(&mut s);
@@ -110,6 +118,16 @@ where
         "divide" => transform::TransformFunction::Divide,
         "round" => transform::TransformFunction::Round,
         "calc_speed" => transform::TransformFunction::CalcSpeed,
+        "rate" => transform::TransformFunction::Rate,
+        "integrate" => transform::TransformFunction::Integrate,
+        "trim" => transform::TransformFunction::Trim,
+        "regex" => transform::TransformFunction::Regex,
+        "parse_number" => transform::TransformFunction::ParseNumber,
+        "hex" => transform::TransformFunction::Hex,
+        "lowercase" => transform::TransformFunction::Lowercase,
+        "uppercase" => transform::TransformFunction::Uppercase,
+        "split" | "index" => transform::TransformFunction::Split,
+        "convert" => transform::TransformFunction::Convert,
         _ => unimplemented!("function {}", func),
     });
 }
@@ -118,11 +136,208 @@ pub fn empty_transform_task() -> EventTransformList {
     return vec![];
 }
 
-#[derive(serde::Deserialize, Debug)]
+// time unit, in seconds
+fn de_time_unit<'de, D>(deserializer: D) -> serde::export::Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let unit = String::deserialize(deserializer).unwrap();
+    return Ok(match unit.to_lowercase().as_str() {
+        "s" | "sec" | "second" => 1.0,
+        "m" | "min" | "minute" => 60.0,
+        "h" | "hour" => 3_600.0,
+        "d" | "day" => 86_400.0,
+        _ => unimplemented!("time unit {}", unit),
+    });
+}
+
+fn get_default_time_unit() -> f64 {
+    1.0
+}
+
+fn get_default_args() -> Vec<f64> {
+    Vec::new()
+}
+
+fn get_default_bits() -> Option<u8> {
+    None
+}
+
+fn get_default_uptime() -> Option<String> {
+    None
+}
+
+lazy_static! {
+    // hashes of set-ids, used as paired uptime values for rate
+    static ref UPTIME_IDS: RwLock<HashSet<u64>> = RwLock::new(HashSet::new());
+}
+
+// the uptime values are recorded for rate, as soon as the config is loaded
+fn de_uptime<'de, D>(deserializer: D) -> serde::export::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let id = String::deserialize(deserializer)?;
+    UPTIME_IDS.write().unwrap().insert(calculate_hash(&id));
+    return Ok(Some(id));
+}
+
+pub fn is_uptime_id(id_hash: u64) -> bool {
+    return UPTIME_IDS.read().unwrap().contains(&id_hash);
+}
+
+fn get_default_persist() -> Option<String> {
+    None
+}
+
+// the persisted integral total is written not more often than this
+const PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+// time of the last write of the persisted totals
+thread_local!(static TOTAL_SAVED: RefCell<HashMap<u64, Instant>> = RefCell::new(HashMap::new()));
+
+// missing or broken file starts the total from zero
+fn load_total(path: &str) -> f64 {
+    return match fs::read_to_string(path) {
+        Ok(v) => match v.trim().parse::<f64>() {
+            Ok(v) => v,
+            Err(_) => {
+                eprint(format!("invalid total in {}, starting from zero", path));
+                0.0
+            }
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => 0.0,
+        Err(e) => {
+            eprint(format!(
+                "unable to read {}: {}, starting from zero",
+                path, e
+            ));
+            0.0
+        }
+    };
+}
+
+// the file is written via a temp one, so it is never left truncated
+fn save_total(path: &str, total: f64) {
+    let tmp = format!("{}.tmp", path);
+    if let Err(e) = fs::write(&tmp, total.to_string()).and_then(|_| fs::rename(&tmp, path)) {
+        eprint(format!("unable to write {}: {}", path, e));
+    }
+}
+
+// the total is loaded on the first call only, zero is returned after
+fn restore_total(uid: u64, path: &str, t: Instant) -> f64 {
+    return TOTAL_SAVED.with(|total_saved_cell| {
+        let mut saved = total_saved_cell.borrow_mut();
+        if saved.contains_key(&uid) {
+            return 0.0;
+        }
+        saved.insert(uid, t);
+        load_total(path)
+    });
+}
+
+fn persist_total(uid: u64, path: &str, total: f64, t: Instant) {
+    TOTAL_SAVED.with(|total_saved_cell| {
+        let mut saved = total_saved_cell.borrow_mut();
+        if let Some(s) = saved.get_mut(&uid) {
+            if t.duration_since(*s) >= PERSIST_INTERVAL {
+                save_total(path, total);
+                *s = t;
+            }
+        }
+    });
+}
+
+fn de_regex<'de, D>(deserializer: D) -> serde::export::Result<Option<transform::Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer).unwrap();
+    return Ok(Some(
+        transform::Regex::new(&pattern)
+            .unwrap_or_else(|e| panic!("invalid regex {}: {}", pattern, e)),
+    ));
+}
+
+fn get_default_pattern() -> Option<transform::Regex> {
+    None
+}
+
+fn get_default_separator() -> Option<String> {
+    None
+}
+
+#[derive(Clone, Copy, Debug)]
+struct UnitConversion {
+    from: &'static transform::Unit,
+    to: &'static transform::Unit,
+}
+
+fn get_conv_unit(unit: &str) -> &'static transform::Unit {
+    return transform::get_unit(unit).unwrap_or_else(|| {
+        let names = transform::get_unit_names(unit);
+        match names.is_empty() {
+            true => panic!("unknown unit {}, see README for the list of units", unit),
+            false => panic!(
+                "unknown unit {}, units are case-sensitive: {}",
+                unit,
+                names.join(", ")
+            ),
+        }
+    });
+}
+
+// "from" and "to" units, checked to be of the same quantity
+fn de_conversion<'de, D>(deserializer: D) -> serde::export::Result<Option<UnitConversion>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    struct Units {
+        from: Option<String>,
+        to: Option<String>,
+    }
+    let units = Units::deserialize(deserializer)?;
+    return Ok(match (units.from, units.to) {
+        (Some(from), Some(to)) => {
+            let (from, to) = (get_conv_unit(&from), get_conv_unit(&to));
+            if from.quantity != to.quantity {
+                panic!("unable to convert {} to {}", from.name(), to.name());
+            }
+            Some(UnitConversion { from, to })
+        }
+        (None, None) => None,
+        _ => panic!("both units to convert from and to must be specified"),
+    });
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
 pub struct EventTransformTask {
     #[serde(deserialize_with = "de_transform_task")]
     func: transform::TransformFunction,
+    #[serde(default = "get_default_args")]
     args: Vec<f64>,
+    // counter width for rate
+    #[serde(default = "get_default_bits")]
+    bits: Option<u8>,
+    #[serde(default = "get_default_time_unit", deserialize_with = "de_time_unit")]
+    unit: f64,
+    // set-id of the paired uptime value for rate reset detection
+    #[serde(default = "get_default_uptime", deserialize_with = "de_uptime")]
+    uptime: Option<String>,
+    // file to keep the integral total in
+    #[serde(default = "get_default_persist")]
+    persist: Option<String>,
+    // regex, the capture group is set in args (default is 1)
+    #[serde(default = "get_default_pattern", deserialize_with = "de_regex")]
+    pattern: Option<transform::Regex>,
+    // split / hex separator
+    #[serde(default = "get_default_separator")]
+    separator: Option<String>,
+    // unit conversion, from / to fields
+    #[serde(flatten, deserialize_with = "de_conversion")]
+    conversion: Option<UnitConversion>,
 }
 
 pub type EventTransformList = Vec<EventTransformTask>;
@@ -143,6 +358,10 @@ impl EventTime {
         };
     }
 
+    pub fn monotonic(&self) -> Instant {
+        return self.monotonic;
+    }
+
     pub fn as_secs(&self) -> f64 {
         return self
             .time
@@ -164,19 +383,377 @@ impl ToString for EventTime {
                     .expect(ERROR_SYSTEM_TIME);
                 let sec = dur.as_secs() as i64;
                 let nsec = dur.subsec_nanos();
-                return Local.timestamp(sec, nsec).to_rfc3339();
+                return match Local.timestamp_opt(sec, nsec).single() {
+                    Some(t) => t.to_rfc3339(),
+                    None => self.as_secs().to_string(),
+                };
             }
             _ => "".to_owned(),
         };
     }
 }
 
+// timestamp data values are numeric for raw time format and RFC3339 strings
+// otherwise. Out of range timestamps are returned as NaN and outputted as
+// invalid values
+pub fn timestamp_value(secs: f64, time_format: TimeFormat) -> Value {
+    return match time_format {
+        TimeFormat::Raw => Value::Number(secs),
+        _ => {
+            let sec = secs.floor();
+            let nsec = ((secs - sec) * 1_000_000_000.0) as u32;
+            match Local.timestamp_opt(sec as i64, nsec).single() {
+                Some(t) => Value::Str(t.to_rfc3339()),
+                None => Value::Number(std::f64::NAN),
+            }
+        }
+    };
+}
+
+// local date/time to timestamp, None if the date/time is invalid
+pub fn datetime_to_timestamp(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    min: u32,
+    sec: u32,
+    usec: u32,
+) -> Option<f64> {
+    return Local
+        .ymd_opt(year, month, day)
+        .single()
+        .and_then(|d| d.and_hms_micro_opt(hour, min, sec, usec))
+        .map(|t| t.timestamp() as f64 + usec as f64 / 1_000_000.0);
+}
+
+// invalid (sentinel) values
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum InvalidAction {
+    Null,
+    Skip,
+}
+
+pub fn de_invalid_action<'de, D>(deserializer: D) -> serde::export::Result<InvalidAction, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let action = String::deserialize(deserializer).unwrap();
+    return Ok(match action.to_lowercase().as_str() {
+        "null" | "none" => InvalidAction::Null,
+        "skip" => InvalidAction::Skip,
+        _ => unimplemented!("invalid value action {}", action),
+    });
+}
+
+pub fn get_default_invalid_values() -> Vec<f64> {
+    Vec::new()
+}
+
+pub fn get_default_invalid_action() -> InvalidAction {
+    InvalidAction::Null
+}
+
+pub fn get_default_stale_after() -> Option<f64> {
+    None
+}
+
+pub fn get_default_stale_repeat() -> Option<f64> {
+    None
+}
+
+pub fn get_default_range_min() -> Option<f64> {
+    None
+}
+
+pub fn get_default_range_max() -> Option<f64> {
+    None
+}
+
+// per-value checks: invalid (sentinel) values, range and frozen signals
+#[derive(Clone, Debug)]
+pub struct ValueChecks {
+    values: Vec<f64>,
+    action: InvalidAction,
+    min: Option<f64>,
+    max: Option<f64>,
+    stale_after: Option<Duration>,
+    stale_repeat: Option<Duration>,
+}
+
+impl ValueChecks {
+    pub fn new(
+        values: Vec<f64>,
+        action: InvalidAction,
+        min: Option<f64>,
+        max: Option<f64>,
+        stale_after: Option<f64>,
+        stale_repeat: Option<f64>,
+    ) -> Self {
+        return Self {
+            values,
+            action,
+            min,
+            max,
+            stale_after: stale_after.map(Duration::from_secs_f64),
+            stale_repeat: stale_repeat.map(Duration::from_secs_f64),
+        };
+    }
+
+    // values are compared after decoding, e.g. 0x8000 is -32768 for int16
+    pub fn contains<T: ToString>(&self, value: &T) -> bool {
+        if self.values.is_empty() {
+            return false;
+        }
+        return match value.to_string().parse::<f64>() {
+            Ok(v) => self.values.contains(&v),
+            Err(_) => false,
+        };
+    }
+
+    pub fn skip(&self) -> bool {
+        return self.action == InvalidAction::Skip;
+    }
+
+    // the range is checked after transformations, non-numeric strings are
+    // always in range
+    pub fn in_range<T: ToString>(&self, value: &T) -> bool {
+        if self.min.is_none() && self.max.is_none() {
+            return true;
+        }
+        return match get_num_value(value) {
+            Some(v) => {
+                !matches!(self.min, Some(min) if v < min)
+                    && !matches!(self.max, Some(max) if v > max)
+            }
+            None => true,
+        };
+    }
+
+    // the value isn't changed since "changed", "reported" is the time of the
+    // last stale event
+    pub fn is_stale(&self, changed: Instant, reported: Option<Instant>, t: Instant) -> bool {
+        return match self.stale_after {
+            Some(after) => match reported {
+                Some(r) => match self.stale_repeat {
+                    Some(repeat) => t.duration_since(r) >= repeat,
+                    None => false,
+                },
+                None => t.duration_since(changed) >= after,
+            },
+            None => false,
+        };
+    }
+}
+
+// NaN and Inf can not be serialized and are handled as invalid values
+pub fn is_finite_value<T: ToString>(value: &T) -> bool {
+    return match value.to_string().parse::<f64>() {
+        Ok(v) => v.is_finite(),
+        Err(_) => true,
+    };
+}
+
+// numeric value for alarms, None if the value is a non-numeric string
+pub fn get_num_value<T: ToString>(value: &T) -> Option<f64> {
+    let s = value.to_string();
+    return match s.parse::<f64>() {
+        Ok(v) => Some(v),
+        Err(_) => s.parse::<bool>().ok().map(|v| if v { 1.0 } else { 0.0 }),
+    };
+}
+
+// data quality, set by pullers and transformations
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Quality {
+    Good,
+    Stale,
+    Bad,
+    CommFailure,
+    OutOfRange,
+    // the device has returned the exception code
+    Exception(u8),
+}
+
+impl Quality {
+    // the event has no value
+    pub fn is_null(&self) -> bool {
+        return matches!(
+            self,
+            Quality::Bad | Quality::CommFailure | Quality::Exception(_)
+        );
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quality::Good => write!(f, "good"),
+            Quality::Stale => write!(f, "stale"),
+            Quality::Bad => write!(f, "bad"),
+            Quality::CommFailure => write!(f, "comm-failure"),
+            Quality::OutOfRange => write!(f, "out-of-range"),
+            Quality::Exception(code) => write!(f, "exception-{}", code),
+        }
+    }
+}
+
+// transformed event value
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    Str(String),
+    Null,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(v) => write!(f, "{}", v),
+            Value::Str(v) => write!(f, "{}", v),
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> serde::export::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::Number(v) => serializer.serialize_f64(*v),
+            Value::Str(v) => serializer.serialize_str(v),
+            Value::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+macro_rules! value_fn {
+    ($self:expr, $f:ident $(, $arg:expr)*) => {
+        match $self {
+            Value::Number(v) => v.$f($($arg),*),
+            Value::Str(v) => v.$f($($arg),*),
+            Value::Null => std::f64::NAN.$f($($arg),*),
+        }
+    };
+}
+
+impl transform::Transform for Value {
+    fn multiply(&self, multiplier: f64) -> f64 {
+        return value_fn!(self, multiply, multiplier);
+    }
+    fn divide(&self, divisor: f64) -> f64 {
+        return value_fn!(self, divide, divisor);
+    }
+    fn round_to(&self, digits: f64) -> f64 {
+        return value_fn!(self, round_to, digits);
+    }
+    fn to_num(&self) -> f64 {
+        return value_fn!(self, to_num);
+    }
+    fn to_bool(&self) -> bool {
+        return value_fn!(self, to_bool);
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        return value_fn!(self, to_bytes);
+    }
+    fn calc_speed(&self, uid: u64, interval: f64, t: Instant) -> Option<f64> {
+        return value_fn!(self, calc_speed, uid, interval, t);
+    }
+    fn calc_rate(
+        &self,
+        uid: u64,
+        bits: Option<u8>,
+        interval: f64,
+        unit: f64,
+        uptime: Option<f64>,
+        t: Instant,
+    ) -> Option<f64> {
+        return value_fn!(self, calc_rate, uid, bits, interval, unit, uptime, t);
+    }
+    fn integrate(&self, uid: u64, unit: f64, initial: f64, t: Instant) -> Option<f64> {
+        return value_fn!(self, integrate, uid, unit, initial, t);
+    }
+}
+
+// raw octet string (SNMP), outputted as UTF-8 if valid, byte-per-char
+// otherwise. The hex transform encodes the original octets
+pub struct OctetString(pub Vec<u8>);
+
+impl fmt::Display for OctetString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match std::str::from_utf8(&self.0) {
+            Ok(v) => write!(f, "{}", v),
+            Err(_) => write!(
+                f,
+                "{}",
+                self.0.iter().map(|b| *b as char).collect::<String>()
+            ),
+        }
+    }
+}
+
+impl Serialize for OctetString {
+    fn serialize<S>(&self, serializer: S) -> serde::export::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl transform::Transform for OctetString {
+    fn multiply(&self, multiplier: f64) -> f64 {
+        return self.to_string().multiply(multiplier);
+    }
+    fn divide(&self, divisor: f64) -> f64 {
+        return self.to_string().divide(divisor);
+    }
+    fn round_to(&self, digits: f64) -> f64 {
+        return self.to_string().round_to(digits);
+    }
+    fn to_num(&self) -> f64 {
+        return self.to_string().to_num();
+    }
+    fn to_bool(&self) -> bool {
+        return self.to_string().to_bool();
+    }
+    fn to_bytes(&self) -> Vec<u8> {
+        return self.0.clone();
+    }
+    fn calc_speed(&self, uid: u64, interval: f64, t: Instant) -> Option<f64> {
+        return self.to_string().calc_speed(uid, interval, t);
+    }
+    fn calc_rate(
+        &self,
+        uid: u64,
+        bits: Option<u8>,
+        interval: f64,
+        unit: f64,
+        uptime: Option<f64>,
+        t: Instant,
+    ) -> Option<f64> {
+        return self
+            .to_string()
+            .calc_rate(uid, bits, interval, unit, uptime, t);
+    }
+    fn integrate(&self, uid: u64, unit: f64, initial: f64, t: Instant) -> Option<f64> {
+        return self.to_string().integrate(uid, unit, initial, t);
+    }
+}
+
 pub struct Event<'a, T: ToString> {
     pub id: &'a String,
     pub id_hash: u64,
     pub value: T,
     pub t: &'a EventTime,
     pub transform_list: &'a EventTransformList,
+    pub alarms: &'a AlarmList,
+    pub checks: &'a ValueChecks,
+    pub unit: Option<&'static str>,
+    pub quality: Quality,
     pub output_flags: OutputFlags,
 }
 
@@ -190,9 +767,14 @@ impl<'a, T: serde::Serialize + std::fmt::Display> Serialize for Event<'a, T> {
             map = serializer.serialize_map(Some(2)).unwrap();
             map.serialize_entry(&self.id, &self.value).unwrap();
         } else {
-            map = serializer.serialize_map(Some(3)).unwrap();
+            map = serializer.serialize_map(Some(5)).unwrap();
             map.serialize_entry("id", &self.id).unwrap();
             map.serialize_entry("value", &self.value).unwrap();
+            if let Some(unit) = self.unit {
+                map.serialize_entry("unit", unit).unwrap();
+            }
+            map.serialize_entry("quality", &self.quality.to_string())
+                .unwrap();
         }
         match self.t.time_format {
             TimeFormat::Raw => map.serialize_entry("time", &self.t.as_secs()).unwrap(),
@@ -208,6 +790,8 @@ impl<'a, T: ToString + transform::Transform> Event<'a, T> {
         id: &'a String,
         value: T,
         transform: &'a EventTransformList,
+        alarms: &'a AlarmList,
+        checks: &'a ValueChecks,
         t: &'a EventTime,
         output_flags: OutputFlags,
     ) -> Self {
@@ -217,28 +801,123 @@ impl<'a, T: ToString + transform::Transform> Event<'a, T> {
             value: value,
             t: t,
             transform_list: transform,
+            alarms: alarms,
+            checks: checks,
+            unit: None,
+            quality: Quality::Good,
             output_flags: output_flags,
         };
     }
-    pub fn transform(
-        &self,
-        transform_function: transform::TransformFunction,
-        args: &Vec<f64>,
-    ) -> Option<Event<f64>> {
-        let value = match transform_function {
-            transform::TransformFunction::Multiply => {
-                Some(self.value.multiply(*args.get(0).unwrap()))
+    // null event with the quality set
+    pub fn invalidate(&self, quality: Quality) -> Event<'_, Value> {
+        return Event {
+            id: &self.id,
+            id_hash: self.id_hash,
+            value: Value::Null,
+            t: self.t,
+            transform_list: &self.transform_list,
+            alarms: self.alarms,
+            checks: self.checks,
+            unit: self.unit,
+            quality: quality,
+            output_flags: self.output_flags,
+        };
+    }
+    pub fn transform(&self, task: &EventTransformTask) -> Option<Event<'_, Value>> {
+        use transform::TransformFunction::*;
+        let args = &task.args;
+        let value = match task.func {
+            Multiply => Some(Value::Number(self.value.multiply(*args.get(0).unwrap()))),
+            Divide => Some(Value::Number(self.value.divide(*args.get(0).unwrap()))),
+            Round => Some(Value::Number(self.value.round_to(*args.get(0).unwrap()))),
+            CalcSpeed => self
+                .value
+                .calc_speed(
+                    self.id_hash,
+                    match args.get(0) {
+                        Some(v) => *v,
+                        None => 1.0,
+                    },
+                    self.t.monotonic,
+                )
+                .map(Value::Number),
+            Rate => self
+                .value
+                .calc_rate(
+                    self.id_hash,
+                    task.bits,
+                    match args.get(0) {
+                        Some(v) => *v,
+                        None => 0.0,
+                    },
+                    task.unit,
+                    match task.uptime {
+                        Some(ref v) => match transform::get_uptime(calculate_hash(v)) {
+                            Some(u) => Some(u),
+                            // a restart can't be detected if the uptime is
+                            // processed after the counter
+                            None => panic!("uptime {} must be pulled before {}", v, self.id),
+                        },
+                        None => None,
+                    },
+                    self.t.monotonic,
+                )
+                .map(Value::Number),
+            Integrate => {
+                let initial = match task.persist {
+                    Some(ref path) => restore_total(self.id_hash, path, self.t.monotonic),
+                    None => 0.0,
+                };
+                let total =
+                    self.value
+                        .integrate(self.id_hash, task.unit, initial, self.t.monotonic);
+                if let (Some(path), Some(v)) = (task.persist.as_ref(), total) {
+                    persist_total(self.id_hash, path, v, self.t.monotonic);
+                }
+                total.map(Value::Number)
             }
-            transform::TransformFunction::Divide => Some(self.value.divide(*args.get(0).unwrap())),
-            transform::TransformFunction::Round => Some(self.value.round_to(*args.get(0).unwrap())),
-            transform::TransformFunction::CalcSpeed => self.value.calc_speed(
-                self.id_hash,
+            Trim => Some(Value::Str(
+                self.value
+                    .to_string()
+                    .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                    .to_owned(),
+            )),
+            Regex => transform::regex_extract(
+                &self.value.to_string(),
+                task.pattern.as_ref().expect("regex pattern not specified"),
                 match args.get(0) {
-                    Some(v) => *v,
-                    None => 1.0,
+                    Some(v) => *v as usize,
+                    None => 1,
                 },
-                self.t.monotonic,
-            ),
+            )
+            .map(Value::Str),
+            ParseNumber => transform::parse_number(&self.value.to_string()).map(Value::Number),
+            Hex => Some(Value::Str(transform::hex_encode(
+                &self.value.to_bytes(),
+                match task.separator {
+                    Some(ref v) => v,
+                    None => "",
+                },
+            ))),
+            Lowercase => Some(Value::Str(self.value.to_string().to_lowercase())),
+            Uppercase => Some(Value::Str(self.value.to_string().to_uppercase())),
+            Split => transform::split_at_index(
+                &self.value.to_string(),
+                task.separator.as_deref(),
+                match args.get(0) {
+                    Some(v) => *v as i64,
+                    None => 0,
+                },
+            )
+            .map(Value::Str),
+            Convert => {
+                let c = task.conversion.expect("units to convert not specified");
+                Some(Value::Number(transform::convert(
+                    self.value.to_num(),
+                    c.from,
+                    c.to,
+                )))
+            }
         };
         return match value {
             Some(v) => Some(Event {
@@ -247,20 +926,26 @@ impl<'a, T: ToString + transform::Transform> Event<'a, T> {
                 value: v,
                 t: self.t,
                 transform_list: &self.transform_list,
+                alarms: self.alarms,
+                checks: self.checks,
+                unit: match task.func {
+                    Convert => task.conversion.map(|c| c.to.name()),
+                    _ => self.unit,
+                },
+                quality: self.quality,
                 output_flags: self.output_flags,
             }),
             None => None,
         };
     }
-    pub fn transform_at(&self, ti: usize) -> Option<Event<f64>> {
-        let tr = self.transform_list.get(ti).unwrap();
-        return self.transform(tr.func, &tr.args);
+    pub fn transform_at(&self, ti: usize) -> Option<Event<'_, Value>> {
+        return self.transform(self.transform_list.get(ti).unwrap());
     }
 }
 
 // work data types
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum GenDataType {
     Bit,
     Int8,
@@ -273,11 +958,55 @@ pub enum GenDataType {
     Int64,
     Real32,
     Real64,
+    // string length (registers for Modbus, max chars for EtherNet/IP)
+    Str(Option<usize>),
+    // string length in bytes, e.g. string(7b)
+    StrBytes(usize),
+    // packed BCD
+    Bcd16,
+    Bcd32,
+    // signed 16/32-bit integer with implied decimal position
+    Fixed(usize),
+    Fixed32(usize),
+    // multi-bit field, set by offset
+    BitField,
+    // timestamps
+    UnixTime32,
+    UnixTime64,
+    UnixTime64Ms,
+    // packed date/time (Y, M, D, h, m, s registers for Modbus, Logix
+    // wall clock DINT[7] for EtherNet/IP)
+    DateTime,
 }
 
+// the config name of the data type
 impl std::fmt::Display for GenDataType {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        return write!(fmt, "{:?}", self);
+        return match self {
+            GenDataType::Bit => write!(fmt, "bit"),
+            GenDataType::Int8 => write!(fmt, "int8"),
+            GenDataType::Int16 => write!(fmt, "int16"),
+            GenDataType::Int32 => write!(fmt, "int32"),
+            GenDataType::Uint8 => write!(fmt, "uint8"),
+            GenDataType::Uint16 => write!(fmt, "uint16"),
+            GenDataType::Uint32 => write!(fmt, "uint32"),
+            GenDataType::Uint64 => write!(fmt, "uint64"),
+            GenDataType::Int64 => write!(fmt, "int64"),
+            GenDataType::Real32 => write!(fmt, "real32"),
+            GenDataType::Real64 => write!(fmt, "real64"),
+            GenDataType::Str(None) => write!(fmt, "string"),
+            GenDataType::Str(Some(len)) => write!(fmt, "string({})", len),
+            GenDataType::StrBytes(len) => write!(fmt, "string({}b)", len),
+            GenDataType::Bcd16 => write!(fmt, "bcd16"),
+            GenDataType::Bcd32 => write!(fmt, "bcd32"),
+            GenDataType::Fixed(decimals) => write!(fmt, "fixed({})", decimals),
+            GenDataType::Fixed32(decimals) => write!(fmt, "fixed32({})", decimals),
+            GenDataType::BitField => write!(fmt, "bit field"),
+            GenDataType::UnixTime32 => write!(fmt, "unixtime32"),
+            GenDataType::UnixTime64 => write!(fmt, "unixtime64"),
+            GenDataType::UnixTime64Ms => write!(fmt, "unixtime64-ms"),
+            GenDataType::DateTime => write!(fmt, "datetime"),
+        };
     }
 }
 
@@ -287,10 +1016,55 @@ pub trait GenDataTypeParse {
 
 impl GenDataTypeParse for String {
     fn parse_data_type(&self) -> GenDataType {
-        return match self.to_lowercase().as_str() {
+        // parametrized types, e.g. string(10), string(20b)
+        let tp = self.to_lowercase();
+        let (name, param) = match tp.find('(') {
+            Some(pos) if tp.ends_with(')') => (&tp[..pos], Some(tp[pos + 1..tp.len() - 1].trim())),
+            _ => (tp.as_str(), None),
+        };
+        let parse_param = |p: &str| {
+            p.trim()
+                .parse::<usize>()
+                .unwrap_or_else(|_| panic!("invalid data type parameter: {}", self))
+        };
+        match name {
+            "string" | "str" => {
+                let tp = match param {
+                    Some(p) if p.ends_with('b') => {
+                        GenDataType::StrBytes(parse_param(&p[..p.len() - 1]))
+                    }
+                    Some(p) => GenDataType::Str(Some(parse_param(p))),
+                    None => GenDataType::Str(None),
+                };
+                if let GenDataType::Str(Some(0)) | GenDataType::StrBytes(0) = tp {
+                    panic!("invalid string length: {}", self);
+                }
+                return tp;
+            }
+            "fixed" | "fixed16" => {
+                return GenDataType::Fixed(
+                    param
+                        .map(parse_param)
+                        .unwrap_or_else(|| panic!("decimal position not specified: {}", self)),
+                )
+            }
+            "fixed32" => {
+                return GenDataType::Fixed32(
+                    param
+                        .map(parse_param)
+                        .unwrap_or_else(|| panic!("decimal position not specified: {}", self)),
+                )
+            }
+            _ => {
+                if param.is_some() {
+                    unimplemented!("Unsupported data type: {}", self);
+                }
+            }
+        }
+        return match name {
             "bit" => GenDataType::Bit,
             "uint8" | "byte" => GenDataType::Uint8,
-            "int8" | "sint8" => GenDataType::Uint8,
+            "int8" | "sint8" => GenDataType::Int8,
             "uint16" | "word" => GenDataType::Uint16,
             "int16" | "sint16" => GenDataType::Int16,
             "uint32" | "dword" => GenDataType::Uint32,
@@ -299,15 +1073,32 @@ impl GenDataTypeParse for String {
             "int64" | "sint64" => GenDataType::Int64,
             "real32" | "real" | "float32" | "float" => GenDataType::Real32,
             "real64" | "float64" => GenDataType::Real64,
+            "bcd16" | "bcd" => GenDataType::Bcd16,
+            "bcd32" => GenDataType::Bcd32,
+            "unixtime32" | "unixtime" => GenDataType::UnixTime32,
+            "unixtime64" => GenDataType::UnixTime64,
+            "unixtime64-ms" | "unixtime64ms" => GenDataType::UnixTime64Ms,
+            "datetime" => GenDataType::DateTime,
             _ => unimplemented!("Unsupported data type: {}", self),
         };
     }
 }
 
-#[derive(Debug)]
+// high or low byte of 16-bit register
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RegisterHalf {
+    High,
+    Low,
+}
+
+#[derive(Clone, Copy, Debug)]
 pub struct DataOffset {
     pub offset: usize,
     pub bit: Option<u8>,
+    // number of bits for X/Y..Z bit fields
+    pub bit_count: Option<u8>,
+    // register half (Modbus), set by the puller
+    pub half: Option<RegisterHalf>,
 }
 
 impl Default for DataOffset {
@@ -315,10 +1106,30 @@ impl Default for DataOffset {
         DataOffset {
             offset: 0,
             bit: None,
+            bit_count: None,
+            half: None,
         }
     }
 }
 
+// array entries must have different set-ids
+pub fn check_array_entry(set_id: &str, count: usize) {
+    if count == 0 {
+        panic!("invalid count of {}: 0", set_id);
+    }
+    if count > 1 && !set_id.contains("{index}") {
+        panic!(
+            "{{index}} not specified in set-id of array entry {}",
+            set_id
+        );
+    }
+}
+
+// set-id template for array entries, e.g. sensor:zone/t{index}.value
+pub fn format_set_id(template: &str, index: usize) -> String {
+    return template.replace("{index}", &index.to_string());
+}
+
 pub trait ParseData {
     fn parse_data_offset(&self, addr: u32) -> DataOffset;
     fn safe_parse_u32(&self) -> u32;
@@ -328,9 +1139,20 @@ impl ParseData for String {
     fn parse_data_offset(&self, addr: u32) -> DataOffset {
         let mut i = self.split("/");
         let mut o = i.next().unwrap().to_string();
-        let bit: Option<u8> = match i.next() {
-            Some(v) => Some(v.parse().unwrap()),
-            None => None,
+        // X/Y..Z (or X/Y-Z) means bits from Y to Z of Xth word
+        let (bit, bit_count): (Option<u8>, Option<u8>) = match i.next() {
+            Some(v) => match v.find("..").map(|p| (p, 2)).or(v.find('-').map(|p| (p, 1))) {
+                Some((pos, sep_len)) => {
+                    let from: u8 = v[..pos].parse().unwrap();
+                    let to: u8 = v[pos + sep_len..].parse().unwrap();
+                    if to < from {
+                        panic!("invalid bit range: {}", self);
+                    }
+                    (Some(from), Some(to - from + 1))
+                }
+                None => (Some(v.parse().unwrap()), None),
+            },
+            None => (None, None),
         };
         let mut offset: u32;
         if o.chars().next().unwrap() == '=' {
@@ -343,6 +1165,8 @@ impl ParseData for String {
         return DataOffset {
             offset: offset as usize,
             bit: bit,
+            bit_count: bit_count,
+            half: None,
         };
     }
     fn safe_parse_u32(&self) -> u32 {
@@ -408,3 +1232,195 @@ impl PullLogEntry {
         self.spent = Some(Instant::now() - self.created);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // applies the transform task (YAML) to the value
+    fn apply_task(task: &str, value: Value, t: &EventTime) -> Option<Value> {
+        return apply_task_unit(task, value, t).map(|v| v.0);
+    }
+
+    // the value and the unit of the transformed event
+    fn apply_task_unit(
+        task: &str,
+        value: Value,
+        t: &EventTime,
+    ) -> Option<(Value, Option<&'static str>)> {
+        let task: EventTransformTask = serde_yaml::from_str(task).unwrap();
+        let id = "test".to_owned();
+        let transform_list = empty_transform_task();
+        let alarms = crate::alarm::empty_alarm_list();
+        let checks = ValueChecks::new(Vec::new(), InvalidAction::Null, None, None, None, None);
+        let event = Event::new(
+            &id,
+            value,
+            &transform_list,
+            &alarms,
+            &checks,
+            t,
+            OutputFlags::empty(),
+        );
+        return event.transform(&task).map(|e| (e.value, e.unit));
+    }
+
+    fn temp_path(name: &str) -> String {
+        return std::env::temp_dir()
+            .join(format!("pulr-test-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned();
+    }
+
+    // outputted with bad quality
+    #[test]
+    fn test_numeric_transform_of_string() {
+        let t = EventTime::new(TimeFormat::Omit);
+        let value = apply_task(
+            "{func: multiply, args: [2]}",
+            Value::Str("n/a".to_owned()),
+            &t,
+        );
+        assert!(!is_finite_value(&value.unwrap()));
+        let value = apply_task("{func: divide, args: [2]}", Value::Str("5".to_owned()), &t);
+        assert!(matches!(value, Some(Value::Number(v)) if v == 2.5));
+    }
+
+    fn convert(task: &str, value: f64) -> (f64, &'static str) {
+        let t = EventTime::new(TimeFormat::Omit);
+        return match apply_task_unit(task, Value::Number(value), &t) {
+            Some((Value::Number(v), Some(unit))) => ((v * 100.0).round() / 100.0, unit),
+            _ => panic!("number with unit expected"),
+        };
+    }
+
+    #[test]
+    fn test_convert_task() {
+        assert_eq!(
+            convert("{func: convert, from: F, to: C}", 100.0),
+            (37.78, "C")
+        );
+        assert_eq!(
+            convert("func: convert\nfrom: °C\nto: kelvin\n", 25.0),
+            (298.15, "K")
+        );
+        assert_eq!(
+            convert("{func: convert, from: kPa, to: bar}", 250.0),
+            (2.5, "bar")
+        );
+        // the flattened fields don't affect other tasks
+        let task: EventTransformTask =
+            serde_yaml::from_str("{func: multiply, args: [2], separator: ','}").unwrap();
+        assert!(task.conversion.is_none());
+        assert_eq!(task.args, vec![2.0]);
+        assert_eq!(task.separator, Some(",".to_owned()));
+    }
+
+    #[test]
+    #[should_panic(expected = "units are case-sensitive: kPa")]
+    fn test_convert_unit_case() {
+        convert("{func: convert, from: Kpa, to: bar}", 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "unable to convert kW to C")]
+    fn test_convert_quantity() {
+        convert("{func: convert, from: kW, to: C}", 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "both units")]
+    fn test_convert_missing_unit() {
+        convert("{func: convert, from: F}", 1.0);
+    }
+
+    #[test]
+    fn test_total_file() {
+        let path = temp_path("total");
+        assert_eq!(load_total(&path), 0.0);
+        save_total(&path, 12.5);
+        assert_eq!(load_total(&path), 12.5);
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        fs::write(&path, "broken").unwrap();
+        assert_eq!(load_total(&path), 0.0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_total_restore() {
+        let path = temp_path("restore");
+        fs::write(&path, "100").unwrap();
+        let task = format!("{{func: integrate, persist: {}}}", path);
+        let t = EventTime::new(TimeFormat::Omit);
+        let value = apply_task(&task, Value::Number(2.0), &t);
+        assert!(matches!(value, Some(Value::Number(v)) if v == 100.0));
+        // the total is not written more often than PERSIST_INTERVAL
+        let uid = calculate_hash(&"test".to_owned());
+        let t0 = t.monotonic();
+        persist_total(uid, &path, 150.0, t0 + Duration::from_secs(5));
+        assert_eq!(load_total(&path), 100.0);
+        persist_total(uid, &path, 150.0, t0 + Duration::from_secs(10));
+        assert_eq!(load_total(&path), 150.0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_data_offset() {
+        let offset = "=3005/2..4".to_owned().parse_data_offset(3000);
+        assert_eq!(offset.offset, 5);
+        assert_eq!(offset.bit, Some(2));
+        assert_eq!(offset.bit_count, Some(3));
+        assert_eq!(offset.half, None);
+    }
+
+    #[test]
+    fn test_value_range() {
+        let checks = ValueChecks::new(
+            Vec::new(),
+            InvalidAction::Null,
+            Some(0.0),
+            Some(100.0),
+            None,
+            None,
+        );
+        assert!(checks.in_range(&Value::Number(0.0)));
+        assert!(checks.in_range(&Value::Number(100.0)));
+        assert!(!checks.in_range(&Value::Number(-0.5)));
+        assert!(!checks.in_range(&Value::Number(100.5)));
+        assert!(checks.in_range(&Value::Str("n/a".to_owned())));
+        let checks = ValueChecks::new(
+            Vec::new(),
+            InvalidAction::Null,
+            None,
+            Some(10.0),
+            None,
+            None,
+        );
+        assert!(checks.in_range(&Value::Number(-1000.0)));
+        assert!(!checks.in_range(&Value::Number(11.0)));
+    }
+
+    #[test]
+    fn test_timestamp_value_out_of_range() {
+        // unixtime64 0xffffffffffffffff
+        match timestamp_value(u64::MAX as f64, TimeFormat::Rfc3339) {
+            Value::Number(v) => assert!(v.is_nan()),
+            _ => panic!("NaN expected"),
+        }
+        assert!(!is_finite_value(&timestamp_value(
+            u64::MAX as f64,
+            TimeFormat::Rfc3339
+        )));
+        match timestamp_value(0.0, TimeFormat::Rfc3339) {
+            Value::Str(_) => {}
+            _ => panic!("string expected"),
+        }
+    }
+
+    // register halves are parsed by Modbus puller only
+    #[test]
+    #[should_panic]
+    fn test_parse_data_offset_half() {
+        "5h".to_owned().parse_data_offset(0);
+    }
+}
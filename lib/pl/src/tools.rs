@@ -39,12 +39,60 @@ impl ParseHostPort for String {
 // bit manipulations
 pub trait GetBit {
     fn get_bit(&self, bit: u8) -> u8;
+    fn get_bits(&self, bit: u8, count: u8) -> Self;
 }
 
 impl GetBit for u16 {
     fn get_bit(&self, bit: u8) -> u8 {
         return (*self >> bit & 1) as u8;
     }
+    fn get_bits(&self, bit: u8, count: u8) -> Self {
+        return ((*self as u32 >> bit) & ((1u32 << count) - 1)) as u16;
+    }
+}
+
+// returns None if the value contains invalid BCD nibbles
+pub fn decode_bcd(value: u64, nibbles: u32) -> Option<u64> {
+    let mut result = 0u64;
+    for i in (0..nibbles).rev() {
+        let digit = (value >> (i * 4)) & 0xf;
+        if digit > 9 {
+            return None;
+        }
+        result = result * 10 + digit;
+    }
+    return Some(result);
+}
+
+pub fn decode_fixed(value: i64, decimals: usize) -> f64 {
+    return value as f64 / 10f64.powi(decimals as i32);
+}
+
+// returns None if the value doesn't fit
+pub fn encode_bcd(value: u64, nibbles: u32) -> Option<u64> {
+    let mut result = 0u64;
+    let mut v = value;
+    for i in 0..nibbles {
+        result |= (v % 10) << (i * 4);
+        v /= 10;
+    }
+    return match v {
+        0 => Some(result),
+        _ => None,
+    };
+}
+
+pub fn encode_fixed(value: f64, decimals: usize) -> i64 {
+    return (value * 10f64.powi(decimals as i32)).round() as i64;
+}
+
+// string data is cut at the first null char, trailing spaces are trimmed
+pub fn decode_str_data(data: &[u8]) -> String {
+    let data = match data.iter().position(|c| *c == 0) {
+        Some(pos) => &data[..pos],
+        None => data,
+    };
+    return String::from_utf8_lossy(data).trim_end().to_owned();
 }
 
 // flushed output
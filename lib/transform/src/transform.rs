@@ -2,6 +2,8 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
+pub use regex::Regex;
+
 #[derive(Debug)]
 struct ValSpeedInfo {
     value: f64,
@@ -52,17 +54,329 @@ fn calculate_growth_speed(
     return speed;
 }
 
+#[derive(Debug)]
+struct ValRateInfo {
+    value: f64,
+    last: Instant,
+    uptime: Option<f64>,
+}
+
+thread_local!(static RATE_INFO: RefCell<HashMap<u64, ValRateInfo>>
+    = RefCell::new(HashMap::new()));
+
+// paired uptime values, recorded by the event processor. Kept apart from the
+// output cache, which is cleared on resend
+thread_local!(static UPTIME_INFO: RefCell<HashMap<u64, f64>>
+    = RefCell::new(HashMap::new()));
+
+pub fn set_uptime(uid: u64, value: f64) {
+    UPTIME_INFO.with(|uptime_info_cell| {
+        uptime_info_cell.borrow_mut().insert(uid, value);
+    });
+}
+
+pub fn get_uptime(uid: u64) -> Option<f64> {
+    return UPTIME_INFO.with(|uptime_info_cell| uptime_info_cell.borrow().get(&uid).copied());
+}
+
+// modulus - counter wrap point (2^bits), None if the counter never wraps
+// unit - rate unit, in seconds
+// uptime - paired uptime value, the counter is reset if it goes down
+//
+// returns None for the first value, if interval not passed yet and if counter
+// reset is detected: the paired uptime goes down or the counter drops less
+// than half of its range (a wrap goes from the top of the range to its bottom)
+fn calculate_rate(
+    value: f64,
+    uid: u64,
+    modulus: Option<f64>,
+    interval: f64,
+    unit: f64,
+    uptime: Option<f64>,
+    t: Instant,
+) -> Option<f64> {
+    let mut rate: Option<f64> = None;
+    RATE_INFO.with(|rate_info_cell| {
+        let mut rinfo = rate_info_cell.borrow_mut();
+        if let Some(prv) = rinfo.get(&uid) {
+            let t_delta = t.duration_since(prv.last).as_secs_f64();
+            if t_delta <= 0.0 || t_delta < interval {
+                return;
+            }
+            let restarted = match (uptime, prv.uptime) {
+                (Some(u), Some(prev_u)) => u < prev_u,
+                _ => false,
+            };
+            if !restarted {
+                let v_delta = if value >= prv.value {
+                    Some(value - prv.value)
+                } else {
+                    match modulus {
+                        Some(m) => {
+                            let d = m - prv.value + value;
+                            if d > m / 2.0 {
+                                None
+                            } else {
+                                Some(d)
+                            }
+                        }
+                        None => None,
+                    }
+                };
+                rate = v_delta.map(|d| d / t_delta * unit);
+            }
+        }
+        rinfo.insert(
+            uid,
+            ValRateInfo {
+                value,
+                last: t,
+                uptime,
+            },
+        );
+    });
+    return rate;
+}
+
+#[derive(Debug)]
+struct ValIntegralInfo {
+    value: f64,
+    last: Instant,
+    total: f64,
+}
+
+thread_local!(static INTEGRAL_INFO: RefCell<HashMap<u64, ValIntegralInfo>>
+    = RefCell::new(HashMap::new()));
+
+// unit - value time unit, in seconds (e.g. 3600 for m3/h -> m3)
+// initial - the total to start from (e.g. persisted), used on the first call
+fn calculate_integral(value: f64, uid: u64, unit: f64, initial: f64, t: Instant) -> Option<f64> {
+    let mut total: Option<f64> = None;
+    INTEGRAL_INFO.with(|integral_info_cell| {
+        let mut iinfo = integral_info_cell.borrow_mut();
+        let tot = match iinfo.get(&uid) {
+            Some(prv) => {
+                let t_delta = t.duration_since(prv.last).as_secs_f64();
+                prv.total + (prv.value + value) / 2.0 * t_delta / unit
+            }
+            None => initial,
+        };
+        iinfo.insert(
+            uid,
+            ValIntegralInfo {
+                value,
+                last: t,
+                total: tot,
+            },
+        );
+        total = Some(tot);
+    });
+    return total;
+}
+
+// string functions
+
+// returns the capture group of the first match, None if nothing matched
+pub fn regex_extract(s: &str, re: &Regex, group: usize) -> Option<String> {
+    return re
+        .captures(s)
+        .and_then(|c| c.get(group))
+        .map(|m| m.as_str().to_owned());
+}
+
+// parses the leading number, stripping unit suffix ("23.5 C", "100%")
+pub fn parse_number(s: &str) -> Option<f64> {
+    let mut n: String = s
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || ['.', '-', '+', 'e', 'E'].contains(c))
+        .collect();
+    while !n.is_empty() {
+        if let Ok(v) = n.parse::<f64>() {
+            return Some(v);
+        }
+        n.pop();
+    }
+    return None;
+}
+
+pub fn hex_encode(data: &[u8], separator: &str) -> String {
+    return data
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(separator);
+}
+
+// negative index counts from the end, None separator splits by whitespace
+pub fn split_at_index(s: &str, separator: Option<&str>, index: i64) -> Option<String> {
+    let parts: Vec<&str> = match separator {
+        Some(sep) => s.split(sep).collect(),
+        None => s.split_whitespace().collect(),
+    };
+    let i = match index < 0 {
+        true => parts.len() as i64 + index,
+        false => index,
+    };
+    if i < 0 {
+        return None;
+    }
+    return parts.get(i as usize).map(|v| (*v).to_owned());
+}
+
+// unit conversion
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    Flow,
+    Volume,
+    Energy,
+    Power,
+    Length,
+    Data,
+}
+
+// value in base units = value * factor + offset
+#[derive(Debug)]
+pub struct Unit {
+    pub names: &'static [&'static str],
+    pub quantity: Quantity,
+    factor: f64,
+    offset: f64,
+}
+
+impl Unit {
+    pub fn name(&self) -> &'static str {
+        return self.names[0];
+    }
+}
+
+macro_rules! unit {
+    ($q:ident, [$($name:expr),+], $factor:expr) => {
+        unit!($q, [$($name),+], $factor, 0.0)
+    };
+    ($q:ident, [$($name:expr),+], $factor:expr, $offset:expr) => {
+        Unit {
+            names: &[$($name),+],
+            quantity: Quantity::$q,
+            factor: $factor,
+            offset: $offset,
+        }
+    };
+}
+
+// base units: K, Pa, m3/s, m3, J, W, m, bit
+static UNITS: &[Unit] = &[
+    unit!(Temperature, ["C", "degC", "°C", "celsius"], 1.0, 273.15),
+    unit!(
+        Temperature,
+        ["F", "degF", "°F", "fahrenheit"],
+        5.0 / 9.0,
+        273.15 - 32.0 * 5.0 / 9.0
+    ),
+    unit!(Temperature, ["K", "kelvin"], 1.0),
+    unit!(Pressure, ["Pa", "pascal"], 1.0),
+    unit!(Pressure, ["hPa"], 100.0),
+    unit!(Pressure, ["kPa"], 1e3),
+    unit!(Pressure, ["MPa"], 1e6),
+    unit!(Pressure, ["bar"], 1e5),
+    unit!(Pressure, ["mbar"], 100.0),
+    unit!(Pressure, ["psi"], 6_894.757_293_168),
+    unit!(Pressure, ["atm"], 101_325.0),
+    unit!(Pressure, ["mmHg", "torr"], 133.322_387_415),
+    unit!(Flow, ["m3/s"], 1.0),
+    unit!(Flow, ["m3/min"], 1.0 / 60.0),
+    unit!(Flow, ["m3/h"], 1.0 / 3_600.0),
+    unit!(Flow, ["l/s"], 1e-3),
+    unit!(Flow, ["l/min"], 1e-3 / 60.0),
+    unit!(Flow, ["l/h"], 1e-3 / 3_600.0),
+    unit!(Flow, ["gpm"], 6.309_019_64e-5),
+    unit!(Flow, ["cfm"], 4.719_474_432e-4),
+    unit!(Volume, ["m3"], 1.0),
+    unit!(Volume, ["l", "liter", "litre"], 1e-3),
+    unit!(Volume, ["ml"], 1e-6),
+    unit!(Volume, ["gal", "gallon"], 3.785_411_784e-3),
+    unit!(Volume, ["ft3"], 0.028_316_846_592),
+    unit!(Energy, ["J", "joule"], 1.0),
+    unit!(Energy, ["kJ"], 1e3),
+    unit!(Energy, ["MJ"], 1e6),
+    unit!(Energy, ["GJ"], 1e9),
+    unit!(Energy, ["Wh"], 3_600.0),
+    unit!(Energy, ["kWh"], 3.6e6),
+    unit!(Energy, ["MWh"], 3.6e9),
+    unit!(Energy, ["cal"], 4.184),
+    unit!(Energy, ["kcal"], 4_184.0),
+    unit!(Energy, ["BTU"], 1_055.055_852_62),
+    unit!(Power, ["W", "watt"], 1.0),
+    unit!(Power, ["kW"], 1e3),
+    unit!(Power, ["MW"], 1e6),
+    unit!(Power, ["hp"], 745.699_871_582_27),
+    unit!(Length, ["m", "meter", "metre"], 1.0),
+    unit!(Length, ["mm"], 1e-3),
+    unit!(Length, ["cm"], 1e-2),
+    unit!(Length, ["km"], 1e3),
+    unit!(Length, ["in", "inch"], 0.0254),
+    unit!(Length, ["ft", "foot", "feet"], 0.3048),
+    unit!(Length, ["yd", "yard"], 0.9144),
+    unit!(Length, ["mi", "mile"], 1_609.344),
+    unit!(Data, ["bit", "bits"], 1.0),
+    unit!(Data, ["kbit"], 1e3),
+    unit!(Data, ["Mbit"], 1e6),
+    unit!(Data, ["Gbit"], 1e9),
+    unit!(Data, ["byte", "bytes", "octet", "octets"], 8.0),
+    unit!(Data, ["kB", "kbyte"], 8e3),
+    unit!(Data, ["MB", "mbyte"], 8e6),
+    unit!(Data, ["GB", "gbyte"], 8e9),
+    unit!(Data, ["KiB"], 8.0 * 1_024.0),
+    unit!(Data, ["MiB"], 8.0 * 1_048_576.0),
+    unit!(Data, ["GiB"], 8.0 * 1_073_741_824.0),
+];
+
+// unit names are case-sensitive, as prefixes are (mW / MW, Mbit / MB)
+pub fn get_unit(name: &str) -> Option<&'static Unit> {
+    return UNITS.iter().find(|u| u.names.contains(&name));
+}
+
+// the names, matching regardless of the case, for error hints
+pub fn get_unit_names(name: &str) -> Vec<&'static str> {
+    return UNITS
+        .iter()
+        .flat_map(|u| u.names.iter())
+        .filter(|n| n.eq_ignore_ascii_case(name))
+        .copied()
+        .collect();
+}
+
+// the units must be of the same quantity
+pub fn convert(value: f64, from: &Unit, to: &Unit) -> f64 {
+    return (value * from.factor + from.offset - to.offset) / to.factor;
+}
+
 pub trait Transform {
     fn multiply(&self, multiplier: f64) -> f64;
     fn divide(&self, divisor: f64) -> f64;
     fn round_to(&self, digits: f64) -> f64;
     fn to_num(&self) -> f64;
     fn to_bool(&self) -> bool;
+    // raw data for hex encoding, strings are UTF-8 encoded
+    fn to_bytes(&self) -> Vec<u8>;
     fn calc_speed(&self, uid: u64, interval: f64, t: Instant) -> Option<f64>;
+    fn calc_rate(
+        &self,
+        uid: u64,
+        bits: Option<u8>,
+        interval: f64,
+        unit: f64,
+        uptime: Option<f64>,
+        t: Instant,
+    ) -> Option<f64>;
+    fn integrate(&self, uid: u64, unit: f64, initial: f64, t: Instant) -> Option<f64>;
 }
 
 macro_rules! impl_Transform_N {
-    ($t:ty, $max:path) => {
+    ($t:ty, $max:path, $bits:expr) => {
         impl Transform for $t {
             fn multiply(&self, multiplier: f64) -> f64 {
                 return *self as f64 * multiplier;
@@ -79,9 +393,27 @@ macro_rules! impl_Transform_N {
             fn to_bool(&self) -> bool {
                 return *self != 0 as $t;
             }
+            fn to_bytes(&self) -> Vec<u8> {
+                return self.to_string().into_bytes();
+            }
             fn calc_speed(&self, uid: u64, interval: f64, t: Instant) -> Option<f64> {
                 return calculate_growth_speed(*self as f64, uid, $max as f64, interval, t);
             }
+            fn calc_rate(
+                &self,
+                uid: u64,
+                bits: Option<u8>,
+                interval: f64,
+                unit: f64,
+                uptime: Option<f64>,
+                t: Instant,
+            ) -> Option<f64> {
+                let modulus = bits.or($bits).map(|b| 2f64.powi(b as i32));
+                return calculate_rate(*self as f64, uid, modulus, interval, unit, uptime, t);
+            }
+            fn integrate(&self, uid: u64, unit: f64, initial: f64, t: Instant) -> Option<f64> {
+                return calculate_integral(*self as f64, uid, unit, initial, t);
+            }
         }
     };
 }
@@ -94,11 +426,9 @@ fn round_to(value: f64, digits: f64) -> f64 {
     return (value * m).round() / m;
 }
 
+// non-numeric strings are NaN, the event is outputted with bad quality
 fn str_to_f64(s: &String) -> f64 {
-    match s.parse::<f64>() {
-        Ok(v) => v,
-        Err(_) => panic!("Unable to parse number: {}", s),
-    }
+    return s.parse::<f64>().unwrap_or(f64::NAN);
 }
 
 impl Transform for String {
@@ -117,8 +447,35 @@ impl Transform for String {
     fn to_bool(&self) -> bool {
         return str_to_f64(self) != 0.0;
     }
-    fn calc_speed(&self, _uid: u64, _interval: f64, _t: Instant) -> Option<f64> {
-        unimplemented!("unable to calculate speed for string");
+    fn to_bytes(&self) -> Vec<u8> {
+        return self.as_bytes().to_vec();
+    }
+    // NaN is not stored, so the calculation continues with the next number
+    fn calc_speed(&self, uid: u64, interval: f64, t: Instant) -> Option<f64> {
+        return match str_to_f64(self) {
+            v if v.is_nan() => Some(v),
+            v => v.calc_speed(uid, interval, t),
+        };
+    }
+    fn calc_rate(
+        &self,
+        uid: u64,
+        bits: Option<u8>,
+        interval: f64,
+        unit: f64,
+        uptime: Option<f64>,
+        t: Instant,
+    ) -> Option<f64> {
+        return match str_to_f64(self) {
+            v if v.is_nan() => Some(v),
+            v => v.calc_rate(uid, bits, interval, unit, uptime, t),
+        };
+    }
+    fn integrate(&self, uid: u64, unit: f64, initial: f64, t: Instant) -> Option<f64> {
+        return match str_to_f64(self) {
+            v if v.is_nan() => Some(v),
+            v => v.integrate(uid, unit, initial, t),
+        };
     }
 }
 
@@ -141,21 +498,38 @@ impl Transform for bool {
     fn to_bool(&self) -> bool {
         return *self;
     }
+    fn to_bytes(&self) -> Vec<u8> {
+        return self.to_string().into_bytes();
+    }
     fn calc_speed(&self, _uid: u64, _interval: f64, _t: Instant) -> Option<f64> {
         unimplemented!("unable to calculate speed for boolean");
     }
+    fn calc_rate(
+        &self,
+        _uid: u64,
+        _bits: Option<u8>,
+        _interval: f64,
+        _unit: f64,
+        _uptime: Option<f64>,
+        _t: Instant,
+    ) -> Option<f64> {
+        unimplemented!("unable to calculate rate for boolean");
+    }
+    fn integrate(&self, _uid: u64, _unit: f64, _initial: f64, _t: Instant) -> Option<f64> {
+        unimplemented!("unable to integrate boolean");
+    }
 }
 
-impl_Transform_N!(i8, std::i8::MAX);
-impl_Transform_N!(u8, std::u8::MAX);
-impl_Transform_N!(i16, std::i16::MAX);
-impl_Transform_N!(u16, std::u16::MAX);
-impl_Transform_N!(i32, std::i32::MAX);
-impl_Transform_N!(u32, std::u32::MAX);
-impl_Transform_N!(i64, std::i64::MAX);
-impl_Transform_N!(u64, std::u64::MAX);
-impl_Transform_N!(f32, std::f32::MAX);
-impl_Transform_N!(f64, std::f64::MAX);
+impl_Transform_N!(i8, std::i8::MAX, None);
+impl_Transform_N!(u8, std::u8::MAX, Some(8));
+impl_Transform_N!(i16, std::i16::MAX, None);
+impl_Transform_N!(u16, std::u16::MAX, Some(16));
+impl_Transform_N!(i32, std::i32::MAX, None);
+impl_Transform_N!(u32, std::u32::MAX, Some(32));
+impl_Transform_N!(i64, std::i64::MAX, None);
+impl_Transform_N!(u64, std::u64::MAX, Some(64));
+impl_Transform_N!(f32, std::f32::MAX, None);
+impl_Transform_N!(f64, std::f64::MAX, None);
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum TransformFunction {
@@ -163,6 +537,139 @@ pub enum TransformFunction {
     Divide,
     Round,
     CalcSpeed,
+    Rate,
+    Integrate,
+    Trim,
+    Regex,
+    ParseNumber,
+    Hex,
+    Lowercase,
+    Uppercase,
+    Split,
+    Convert,
     //To_Num,
     //To_Bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(t: Instant, s: f64) -> Instant {
+        return t + Duration::from_secs_f64(s);
+    }
+
+    #[test]
+    fn test_non_numeric_string() {
+        let s = "n/a".to_owned();
+        assert!(s.multiply(2.0).is_nan());
+        assert!(s.round_to(1.0).is_nan());
+        let t = Instant::now();
+        assert!(s.calc_rate(10, None, 0.0, 1.0, None, t).unwrap().is_nan());
+        // numeric strings are calculated as numbers
+        assert_eq!("2.5".to_owned().multiply(2.0), 5.0);
+        assert_eq!("5".to_owned().calc_rate(10, None, 0.0, 1.0, None, t), None);
+    }
+
+    #[test]
+    fn test_integral() {
+        let t = Instant::now();
+        assert_eq!(calculate_integral(10.0, 1, 1.0, 5.0, t), Some(5.0));
+        // trapezoid: (10 + 20) / 2 * 2s
+        assert_eq!(
+            calculate_integral(20.0, 1, 1.0, 0.0, secs(t, 2.0)),
+            Some(35.0)
+        );
+        // m3/h for 30 minutes
+        assert_eq!(calculate_integral(4.0, 2, 3600.0, 0.0, t), Some(0.0));
+        assert_eq!(
+            calculate_integral(4.0, 2, 3600.0, 0.0, secs(t, 1800.0)),
+            Some(2.0)
+        );
+    }
+
+    #[test]
+    fn test_rate() {
+        let t = Instant::now();
+        assert_eq!(calculate_rate(100.0, 1, None, 0.0, 1.0, None, t), None);
+        assert_eq!(
+            calculate_rate(150.0, 1, None, 0.0, 1.0, None, secs(t, 0.5)),
+            Some(100.0)
+        );
+        // per minute
+        assert_eq!(
+            calculate_rate(200.0, 1, None, 0.0, 60.0, None, secs(t, 1.0)),
+            Some(6000.0)
+        );
+    }
+
+    #[test]
+    fn test_rate_wraparound() {
+        let t = Instant::now();
+        let m = Some(65536.0);
+        calculate_rate(65500.0, 2, m, 0.0, 1.0, None, t);
+        assert_eq!(
+            calculate_rate(64.0, 2, m, 0.0, 1.0, None, secs(t, 1.0)),
+            Some(100.0)
+        );
+    }
+
+    #[test]
+    fn test_rate_reset() {
+        let t = Instant::now();
+        let m = Some(65536.0);
+        calculate_rate(30000.0, 3, m, 0.0, 1.0, None, t);
+        // the drop is less than half of the range
+        assert_eq!(
+            calculate_rate(10.0, 3, m, 0.0, 1.0, None, secs(t, 1.0)),
+            None
+        );
+        assert_eq!(
+            calculate_rate(20.0, 3, m, 0.0, 1.0, None, secs(t, 2.0)),
+            Some(10.0)
+        );
+        // counters without width never wrap
+        calculate_rate(100.0, 4, None, 0.0, 1.0, None, t);
+        assert_eq!(
+            calculate_rate(90.0, 4, None, 0.0, 1.0, None, secs(t, 1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rate_uptime_restart() {
+        let t = Instant::now();
+        let m = Some(65536.0);
+        calculate_rate(65500.0, 5, m, 0.0, 1.0, Some(1000.0), t);
+        // looks like a wrap, but the device has been restarted
+        assert_eq!(
+            calculate_rate(64.0, 5, m, 0.0, 1.0, Some(2.0), secs(t, 1.0)),
+            None
+        );
+        assert_eq!(
+            calculate_rate(74.0, 5, m, 0.0, 1.0, Some(3.0), secs(t, 2.0)),
+            Some(10.0)
+        );
+        // the same wrap without restart
+        calculate_rate(65530.0, 5, m, 0.0, 1.0, Some(4.0), secs(t, 4.0));
+        assert_eq!(
+            calculate_rate(64.0, 5, m, 0.0, 1.0, Some(5.0), secs(t, 5.0)),
+            Some(70.0)
+        );
+    }
+
+    #[test]
+    fn test_rate_interval() {
+        let t = Instant::now();
+        calculate_rate(0.0, 6, None, 2.0, 1.0, None, t);
+        assert_eq!(
+            calculate_rate(10.0, 6, None, 2.0, 1.0, None, secs(t, 1.0)),
+            None
+        );
+        // the skipped value doesn't move the base
+        assert_eq!(
+            calculate_rate(40.0, 6, None, 2.0, 1.0, None, secs(t, 2.0)),
+            Some(20.0)
+        );
+    }
+}
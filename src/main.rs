@@ -8,9 +8,19 @@ use std::{env, fs, io, io::Read};
 #[macro_use]
 mod common;
 
+#[path = "proto/command.rs"]
+mod command;
+
+#[cfg(unix)]
+#[path = "proto/serial.rs"]
+mod serial;
+
 #[path = "proto/ppmodbus.rs"]
 mod ppmodbus;
 
+#[path = "proto/server.rs"]
+mod server;
+
 #[path = "proto/ppenip.rs"]
 mod ppenip;
 
@@ -85,9 +95,78 @@ struct Config {
         deserialize_with = "de_time_format"
     )]
     time_format: pl::datatypes::TimeFormat,
+    server: Option<server::ServerConfig>,
+}
+
+// pulr scan <proto> <host:port> [options]
+fn scan(args: Vec<String>) {
+    let mut proto = String::new();
+    let mut target = String::new();
+    let mut units = "1".to_owned();
+    let mut ranges = "h0-999,i0-999,c0-999,d0-999".to_owned();
+    let mut block: u16 = 100;
+    let mut timeout: f32 = 1.0;
+    let mut yaml = false;
+    let mut verbose = false;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Scan the device for unit IDs and registers");
+        ap.refer(&mut proto)
+            .add_argument("PROTO", Store, "Protocol (modbus, modbus/tcp, modbus/udp)")
+            .required();
+        ap.refer(&mut target)
+            .add_argument("TARGET", Store, "Device host:port")
+            .required();
+        ap.refer(&mut units)
+            .add_option(&["-u", "--units"], Store, "Unit IDs, e.g. 1-10,17")
+            .metavar("UNITS");
+        ap.refer(&mut ranges)
+            .add_option(&["-r", "--ranges"], Store, "Register ranges")
+            .metavar("RANGES");
+        ap.refer(&mut block)
+            .add_option(&["-b", "--block"], Store, "Registers (coils) per request")
+            .metavar("N");
+        ap.refer(&mut timeout)
+            .add_option(&["-t", "--timeout"], Store, "Request timeout")
+            .metavar("SEC");
+        ap.refer(&mut yaml).add_option(
+            &["-y", "--yaml"],
+            StoreTrue,
+            "Output skeleton pull config (the report goes to stderr)",
+        );
+        ap.refer(&mut verbose).add_option(
+            &["-v", "--verbose"],
+            StoreTrue,
+            "Verbose output (debug)",
+        );
+        if let Err(code) = ap.parse(args, &mut io::stdout(), &mut io::stderr()) {
+            std::process::exit(code);
+        }
+    }
+    pl::init();
+    match proto.split('/').next().unwrap() {
+        "modbus" => ppmodbus::scan(
+            &proto,
+            &target,
+            &units,
+            &ranges,
+            block,
+            Duration::from_micros((timeout as f64 * 1_000_000.0) as u64),
+            yaml,
+            verbose,
+        ),
+        _ => unimplemented!("scan for {}", proto),
+    }
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|v| v.as_str()) == Some("scan") {
+        let mut scan_args = vec![format!("{} scan", args[0])];
+        scan_args.extend_from_slice(&args[2..]);
+        scan(scan_args);
+        return;
+    }
     let mut in_loop = false;
     let mut verbose = false;
     let mut cfgfile = String::new();
@@ -152,6 +231,9 @@ fn main() {
         }
         let core = pl::Core::new(otp.0, otp.1, config.time_format, etimeout);
         let mut beacon = pl::Beacon::new(otp.0, beacon_interval);
+        if let Some(server_config) = config.server {
+            server::start(server_config, verbose);
+        }
 
         match proto_name {
             "modbus" => {
@@ -0,0 +1,216 @@
+// write command channel: stdin lines, a Unix socket or a local HTTP endpoint
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+pub struct Command {
+    pub line: String,
+    // the result is sent back to socket / HTTP clients
+    reply: Option<mpsc::Sender<Result<(), String>>>,
+}
+
+impl Command {
+    pub fn reply(&self, result: Result<(), String>) {
+        if let Some(ref tx) = self.reply {
+            let _ = tx.send(result);
+        }
+    }
+}
+
+// send the line to the pull loop and wait for the result
+fn process_line(line: &str, commands: &mpsc::Sender<Command>) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    if commands
+        .send(Command {
+            line: line.to_owned(),
+            reply: Some(tx),
+        })
+        .is_err()
+    {
+        return Err("terminated".to_owned());
+    }
+    return match rx.recv() {
+        Ok(v) => v,
+        Err(_) => Err("terminated".to_owned()),
+    };
+}
+
+fn format_result(result: Result<(), String>) -> String {
+    return match result {
+        Ok(_) => "ok".to_owned(),
+        Err(e) => format!("error: {}", e),
+    };
+}
+
+#[cfg(unix)]
+fn handle_unix_client(stream: UnixStream, commands: mpsc::Sender<Command>) {
+    let mut writer = match stream.try_clone() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = format_result(process_line(&line, &commands));
+        if writeln!(writer, "{}", result).is_err() {
+            return;
+        }
+    }
+}
+
+// command lines are short, larger bodies are rejected without reading
+const MAX_HTTP_BODY: usize = 4096;
+// request line and headers
+const MAX_HTTP_HEAD: usize = 8192;
+const MAX_HTTP_CLIENTS: usize = 16;
+// seconds, slow clients are disconnected
+const HTTP_TIMEOUT: u64 = 5;
+
+// POST with command lines in the body, results are returned line-by-line
+fn handle_http_client(stream: TcpStream, commands: mpsc::Sender<Command>) {
+    let mut writer = match stream.try_clone() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream.take((MAX_HTTP_HEAD + MAX_HTTP_BODY) as u64));
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(pos) = header.find(':') {
+            if header[..pos].eq_ignore_ascii_case("content-length") {
+                content_length = header[pos + 1..].trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let (code, body) = match request_line.split_whitespace().next() {
+        Some("POST") if content_length > MAX_HTTP_BODY => (
+            "413 Payload Too Large",
+            format!("error: body is larger than {} bytes\n", MAX_HTTP_BODY),
+        ),
+        Some("POST") => {
+            let mut buf = vec![0; content_length];
+            if reader.read_exact(&mut buf).is_err() {
+                return;
+            }
+            let mut code = "200 OK";
+            let mut result = String::new();
+            for line in String::from_utf8_lossy(&buf).lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let r = process_line(line, &commands);
+                if r.is_err() {
+                    code = "400 Bad Request";
+                }
+                result += &format_result(r);
+                result.push('\n');
+            }
+            (code, result)
+        }
+        _ => ("405 Method Not Allowed", "error: use POST\n".to_owned()),
+    };
+    let _ = write!(
+        writer,
+        "HTTP/1.0 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+        code,
+        body.len(),
+        body
+    );
+}
+
+// "stdin", "http://host:port" or the Unix socket path
+pub fn listen(source: &str) -> mpsc::Receiver<Command> {
+    let (tx, rx) = mpsc::channel();
+    if source == "stdin" {
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let line = line.unwrap();
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // results are outputted as events only
+                if tx.send(Command { line, reply: None }).is_err() {
+                    break;
+                }
+            }
+        });
+    } else if source.starts_with("http://") {
+        let addr = source.trim_start_matches("http://");
+        // commands aren't authenticated, the endpoint must be local
+        if !addr
+            .to_socket_addrs()
+            .expect("invalid command HTTP endpoint")
+            .all(|a| a.ip().is_loopback())
+        {
+            panic!("command HTTP endpoint must be a loopback address: {}", addr);
+        }
+        let listener = TcpListener::bind(addr).expect("unable to bind command HTTP endpoint");
+        let timeout = Some(Duration::from_secs(HTTP_TIMEOUT));
+        let clients = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if clients.load(Ordering::SeqCst) >= MAX_HTTP_CLIENTS {
+                    continue;
+                }
+                if stream.set_read_timeout(timeout).is_err()
+                    || stream.set_write_timeout(timeout).is_err()
+                {
+                    continue;
+                }
+                clients.fetch_add(1, Ordering::SeqCst);
+                let tx = tx.clone();
+                let clients = clients.clone();
+                thread::spawn(move || {
+                    handle_http_client(stream, tx);
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+    } else {
+        #[cfg(unix)]
+        {
+            // remove the socket, left by the previous run
+            if let Ok(meta) = std::fs::metadata(source) {
+                if meta.file_type().is_socket() {
+                    let _ = std::fs::remove_file(source);
+                }
+            }
+            let listener = UnixListener::bind(source).expect("unable to bind command socket");
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let tx = tx.clone();
+                    thread::spawn(move || handle_unix_client(stream, tx));
+                }
+            });
+        }
+        #[cfg(not(unix))]
+        unimplemented!("command source {}", source);
+    }
+    return rx;
+}
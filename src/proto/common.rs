@@ -5,6 +5,11 @@ macro_rules! define_task_result {
             Process,
             #[allow(dead_code)]
             ClearCache,
+            // the pull has failed, output all its values with the quality
+            Fail(pl::datatypes::Quality),
+            // write command result for the target
+            #[allow(dead_code)]
+            Ack(String, Result<(), String>),
             Terminate,
         }
         struct TaskResult {
@@ -1,3 +1,4 @@
+use pl::tools::{decode_bcd, decode_fixed, decode_str_data, eprint};
 use pl::IntervalLoop;
 use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
@@ -6,11 +7,14 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant, SystemTime};
 
+use pl::alarm;
 use pl::datatypes;
 use pl::datatypes::{GenDataType, GenDataTypeParse, ParseData};
 
 const DEFAULT_ENIP_PORT: u16 = 44818;
 const PLC_SLEEP_STEP: u32 = 10_000_000;
+const LOGIX_STRING_LEN: usize = 82;
+const LOGIX_STRING_SIZE: usize = 88;
 
 #[derive(Deserialize)]
 struct EnIpConfig {
@@ -47,6 +51,22 @@ struct EnIpPull {
     process: Vec<EnIpProcess>,
 }
 
+fn get_default_bits() -> Option<String> {
+    None
+}
+
+fn get_default_process_count() -> usize {
+    1
+}
+
+fn get_default_stride() -> Option<usize> {
+    None
+}
+
+fn get_default_start_index() -> usize {
+    0
+}
+
 fn get_default_type() -> String {
     "bit".to_owned()
 }
@@ -54,12 +74,48 @@ fn get_default_type() -> String {
 #[derive(Deserialize)]
 struct EnIpProcess {
     offset: String,
+    // bit range, Y-Z or Y..Z, same as offset X/Y..Z
+    #[serde(default = "get_default_bits")]
+    bits: Option<String>,
+    // array entries
+    #[serde(default = "get_default_process_count")]
+    count: usize,
+    #[serde(default = "get_default_stride")]
+    stride: Option<usize>,
+    #[serde(alias = "start-index", default = "get_default_start_index")]
+    start_index: usize,
     #[serde(alias = "type", default = "get_default_type")]
     r#type: String,
     #[serde(alias = "set-id")]
     set_id: String,
     #[serde(default = "datatypes::empty_transform_task")]
     transform: datatypes::EventTransformList,
+    #[serde(default = "alarm::empty_alarm_list")]
+    alarms: alarm::AlarmList,
+    // sentinel values, reported as null
+    #[serde(default = "datatypes::get_default_invalid_values")]
+    invalid: Vec<f64>,
+    #[serde(
+        alias = "on-invalid",
+        default = "datatypes::get_default_invalid_action",
+        deserialize_with = "datatypes::de_invalid_action"
+    )]
+    on_invalid: datatypes::InvalidAction,
+    // output "out-of-range" quality if the value (after transformations) is
+    // out of range
+    #[serde(default = "datatypes::get_default_range_min")]
+    min: Option<f64>,
+    #[serde(default = "datatypes::get_default_range_max")]
+    max: Option<f64>,
+    // seconds, output "stale" quality event if the value isn't changed
+    #[serde(alias = "stale-after", default = "datatypes::get_default_stale_after")]
+    stale_after: Option<f64>,
+    // seconds, repeat the stale event
+    #[serde(
+        alias = "stale-repeat",
+        default = "datatypes::get_default_stale_repeat"
+    )]
+    stale_repeat: Option<f64>,
 }
 
 // TODO: move some fields to de_
@@ -74,6 +130,8 @@ struct EnIpDataProcessInfo {
     tp: datatypes::GenDataType,
     set_id: String,
     transform: datatypes::EventTransformList,
+    alarms: alarm::AlarmList,
+    checks: datatypes::ValueChecks,
 }
 
 define_task_result!(i32);
@@ -86,6 +144,48 @@ fn safe_get_bit(tag: i32, bit_offset: i32) -> u8 {
     result as u8
 }
 
+// tag data size in bytes, bit entries use 1 bit
+fn get_type_size(tp: GenDataType) -> usize {
+    match tp {
+        GenDataType::Int16 | GenDataType::Uint16 | GenDataType::Bcd16 | GenDataType::Fixed(_) => 2,
+        GenDataType::Int32
+        | GenDataType::Uint32
+        | GenDataType::Real32
+        | GenDataType::Bcd32
+        | GenDataType::Fixed32(_)
+        | GenDataType::UnixTime32 => 4,
+        GenDataType::Int64
+        | GenDataType::Uint64
+        | GenDataType::Real64
+        | GenDataType::UnixTime64
+        | GenDataType::UnixTime64Ms => 8,
+        GenDataType::DateTime => 28,
+        _ => 1,
+    }
+}
+
+fn get_bit_field(tag: i32, bit_offset: i32, count: u8) -> u32 {
+    let mut result = 0u32;
+    for i in (0..count as i32).rev() {
+        result = result << 1 | safe_get_bit(tag, bit_offset + i) as u32;
+    }
+    result
+}
+
+// Logix STRING: DINT length + SINT data
+fn get_logix_string(tag: i32, offset: usize, max_len: Option<usize>) -> String {
+    let len = unsafe { plctag::plc_tag_get_int32(tag, offset as i32) };
+    let len = std::cmp::min(
+        std::cmp::max(len, 0) as usize,
+        max_len.unwrap_or(LOGIX_STRING_LEN),
+    );
+    let mut data = Vec::with_capacity(len);
+    for i in 0..len {
+        data.push(unsafe { plctag::plc_tag_get_uint8(tag, (offset + 4 + i) as i32) });
+    }
+    decode_str_data(&data)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn run(
     inloop: bool,
@@ -121,26 +221,67 @@ pub fn run(
     for p in config.pull {
         let mut process_data_vec: Vec<EnIpDataProcessInfo> = Vec::new();
         for prc in p.process {
-            let mut offset = prc.offset.parse_data_offset(0);
+            datatypes::check_array_entry(&prc.set_id, prc.count);
+            let mut offset = match prc.bits {
+                Some(ref bits) => format!("{}/{}", prc.offset, bits),
+                None => prc.offset,
+            }
+            .parse_data_offset(0);
             let tp = match offset.bit {
                 Some(_) => {
                     offset.offset *= 8;
-                    datatypes::GenDataType::Bit
-                }
-                None => {
-                    let p = prc.r#type.parse_data_type();
-                    if p == datatypes::GenDataType::Bit {
-                        panic!("data type not specified");
+                    match offset.bit_count {
+                        Some(count) => {
+                            if count > 32 {
+                                panic!("bit field is too long: {}", prc.set_id);
+                            }
+                            datatypes::GenDataType::BitField
+                        }
+                        None => datatypes::GenDataType::Bit,
                     }
-                    p
                 }
+                None => match prc.r#type.parse_data_type() {
+                    datatypes::GenDataType::Bit => panic!("data type not specified"),
+                    // Logix string chars are bytes
+                    datatypes::GenDataType::StrBytes(len) => datatypes::GenDataType::Str(Some(len)),
+                    p => p,
+                },
+            };
+            // stride is set in bits for bit entries and in bytes for others
+            let stride = match prc.stride {
+                Some(v) => v,
+                None => match tp {
+                    GenDataType::BitField => offset.bit_count.unwrap() as usize,
+                    GenDataType::Str(None) => LOGIX_STRING_SIZE,
+                    GenDataType::Str(Some(_)) => {
+                        if prc.count > 1 {
+                            panic!("stride not specified for {}", prc.set_id);
+                        }
+                        0
+                    }
+                    _ => get_type_size(tp),
+                },
             };
-            process_data_vec.push(EnIpDataProcessInfo {
-                offset,
-                set_id: prc.set_id,
-                tp,
-                transform: prc.transform,
-            });
+            let checks = datatypes::ValueChecks::new(
+                prc.invalid,
+                prc.on_invalid,
+                prc.min,
+                prc.max,
+                prc.stale_after,
+                prc.stale_repeat,
+            );
+            for i in 0..prc.count {
+                let mut o = offset;
+                o.offset += i * stride;
+                process_data_vec.push(EnIpDataProcessInfo {
+                    offset: o,
+                    set_id: datatypes::format_set_id(&prc.set_id, prc.start_index + i),
+                    tp,
+                    transform: prc.transform.clone(),
+                    alarms: prc.alarms.clone(),
+                    checks: checks.clone(),
+                });
+            }
         }
         let path = format!(
             "{}&elem_size={}{}&name={}",
@@ -170,6 +311,20 @@ pub fn run(
                     core.clear_event_cache();
                     continue;
                 }
+                TaskCmd::Fail(quality) => {
+                    for d in dp_list.get(w.work_id.unwrap()).unwrap() {
+                        let event = core.create_event(
+                            &d.set_id,
+                            datatypes::Value::Null,
+                            &d.transform,
+                            &d.alarms,
+                            &d.checks,
+                            &t,
+                        );
+                        core.output_quality(&event, quality);
+                    }
+                    continue;
+                }
                 TaskCmd::Terminate => break,
                 _ => continue,
             },
@@ -177,9 +332,14 @@ pub fn run(
         let i = w.work_id.unwrap();
         for d in dp_list.get(i).unwrap() {
             macro_rules! process_tag {
-                ($fn:path, $offset:expr) => {
-                    let event =
-                        core.create_event(&d.set_id, $fn(tag_id, $offset as i32), &d.transform, &t);
+                ($fn:path, $offset:expr $(, $arg:expr)*) => {
+                    let event = core.create_event(
+                        &d.set_id,
+                        $fn(tag_id, $offset as i32 $(, $arg)*),
+                        &d.transform,
+                        &d.alarms, &d.checks,
+                        &t,
+                    );
                     core.output(&event);
                 };
             }
@@ -214,6 +374,162 @@ pub fn run(
                 GenDataType::Real64 => unsafe {
                     process_tag!(plctag::plc_tag_get_float64, d.offset.offset);
                 },
+                GenDataType::Bcd16 | GenDataType::Bcd32 => {
+                    let (raw, nibbles) = unsafe {
+                        match d.tp {
+                            GenDataType::Bcd16 => (
+                                plctag::plc_tag_get_uint16(tag_id, d.offset.offset as i32) as u64,
+                                4,
+                            ),
+                            _ => (
+                                plctag::plc_tag_get_uint32(tag_id, d.offset.offset as i32) as u64,
+                                8,
+                            ),
+                        }
+                    };
+                    match decode_bcd(raw, nibbles) {
+                        Some(value) => {
+                            let event = core.create_event(
+                                &d.set_id,
+                                value,
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output(&event);
+                        }
+                        // the value is outputted as bad, not kept
+                        None => {
+                            eprint(format!(
+                                "decode error: {} invalid BCD value 0x{:x}",
+                                d.set_id, raw
+                            ));
+                            let event = core.create_event(
+                                &d.set_id,
+                                datatypes::Value::Null,
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output_quality(&event, datatypes::Quality::Bad);
+                        }
+                    }
+                }
+                GenDataType::Fixed(decimals) => {
+                    let raw = unsafe { plctag::plc_tag_get_int16(tag_id, d.offset.offset as i32) };
+                    let event = core.create_event(
+                        &d.set_id,
+                        decode_fixed(raw as i64, decimals),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::Fixed32(decimals) => {
+                    let raw = unsafe { plctag::plc_tag_get_int32(tag_id, d.offset.offset as i32) };
+                    let event = core.create_event(
+                        &d.set_id,
+                        decode_fixed(raw as i64, decimals),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::UnixTime32 => {
+                    let raw = unsafe { plctag::plc_tag_get_uint32(tag_id, d.offset.offset as i32) };
+                    let event = core.create_event(
+                        &d.set_id,
+                        core.timestamp_value(raw as f64),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::UnixTime64 | GenDataType::UnixTime64Ms => {
+                    let raw = unsafe { plctag::plc_tag_get_int64(tag_id, d.offset.offset as i32) };
+                    let mut secs = raw as f64;
+                    if d.tp == GenDataType::UnixTime64Ms {
+                        secs /= 1_000.0;
+                    }
+                    let event = core.create_event(
+                        &d.set_id,
+                        core.timestamp_value(secs),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::DateTime => {
+                    let mut r = [0i32; 7];
+                    for (i, v) in r.iter_mut().enumerate() {
+                        *v = unsafe {
+                            plctag::plc_tag_get_int32(tag_id, (d.offset.offset + i * 4) as i32)
+                        };
+                    }
+                    match datatypes::datetime_to_timestamp(
+                        r[0],
+                        r[1] as u32,
+                        r[2] as u32,
+                        r[3] as u32,
+                        r[4] as u32,
+                        r[5] as u32,
+                        r[6] as u32,
+                    ) {
+                        Some(secs) => {
+                            let event = core.create_event(
+                                &d.set_id,
+                                core.timestamp_value(secs),
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output(&event);
+                        }
+                        None => {
+                            eprint(format!("decode error: {} invalid date {:?}", d.set_id, r));
+                            let event = core.create_event(
+                                &d.set_id,
+                                datatypes::Value::Null,
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output_quality(&event, datatypes::Quality::Bad);
+                        }
+                    }
+                }
+                GenDataType::Str(len) => {
+                    let event = core.create_event(
+                        &d.set_id,
+                        get_logix_string(tag_id, d.offset.offset, len),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                // converted to Str when the config is loaded
+                GenDataType::StrBytes(_) => unreachable!(),
+                GenDataType::BitField => {
+                    process_tag!(
+                        get_bit_field,
+                        d.offset.offset as u32 + d.offset.bit.unwrap() as u32,
+                        d.offset.bit_count.unwrap()
+                    );
+                }
                 GenDataType::Bit => {
                     process_tag!(
                         safe_get_bit,
@@ -275,21 +591,31 @@ pub fn run(
                     tag_id
                 },
             };
-            unsafe {
+            let rc = unsafe {
                 if verbose {
                     pl::print_debug(&format!("reading tag {}", p.path));
                 }
-                let rc = plctag::plc_tag_read(tag_id, plc_timeout);
-                if rc != plctag::PLCTAG_STATUS_OK {
-                    panic!("{} read error {}", p.path, rc);
-                }
-            }
+                plctag::plc_tag_read(tag_id, plc_timeout)
+            };
             log_pulled!(pull_log_entry);
-            tx.send(TaskResult {
-                data: Some(tag_id),
-                work_id: Some(work_id),
-                t: call_time,
-                cmd: TaskCmd::Process,
+            tx.send(match rc {
+                plctag::PLCTAG_STATUS_OK => TaskResult {
+                    data: Some(tag_id),
+                    work_id: Some(work_id),
+                    t: call_time,
+                    cmd: TaskCmd::Process,
+                },
+                _ => {
+                    if verbose {
+                        pl::print_debug(&format!("{} read error {}", p.path, rc));
+                    }
+                    TaskResult {
+                        data: None,
+                        work_id: Some(work_id),
+                        t: call_time,
+                        cmd: TaskCmd::Fail(datatypes::Quality::CommFailure),
+                    }
+                }
             })
             .unwrap();
             if verbose_warnings {
@@ -3,6 +3,7 @@ use serde::{Deserialize, Deserializer};
 use std::io::{Read, Write};
 use std::net::{SocketAddr, TcpStream, UdpSocket};
 
+use std::collections::{BTreeMap, HashMap};
 use std::panic::panic_any;
 use std::sync::mpsc;
 
@@ -11,19 +12,37 @@ use ieee754::Ieee754;
 use std::thread;
 use std::time::{Duration, Instant};
 
-use pl::tools::GetBit;
+use pl::tools::{
+    decode_bcd, decode_fixed, decode_str_data, encode_bcd, encode_fixed, eprint, oprint, GetBit,
+};
 use pl::IntervalLoop;
 
 use datatypes::{GenDataType, GenDataTypeParse, ParseData};
+use pl::alarm;
 use pl::datatypes;
 
-use rmodbus::{client::ModbusRequest, guess_response_frame_len, ModbusFrameBuf, ModbusProto};
+use crate::command;
+#[cfg(unix)]
+use crate::serial::SerialPort;
+
+use rmodbus::{
+    client::ModbusRequest, generate_ascii_frame, parse_ascii_frame, ModbusFrameBuf, ModbusProto,
+};
 
 const DEFAULT_MODBUS_PORT: u16 = 502;
 
 const ERROR_OOB: &str = "data out of bounds";
 
-#[derive(Debug)]
+// functions, not supported by rmodbus request generator
+const MODBUS_READ_WRITE_HOLDINGS: u8 = 0x17;
+const MODBUS_READ_FIFO: u8 = 0x18;
+const MODBUS_ENCAPSULATED: u8 = 0x2b;
+const MEI_READ_DEVICE_ID: u8 = 0x0e;
+
+// FIFO pulls return the value count and up to 31 values
+const FIFO_PULL_SIZE: u16 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum ModbusRegisterType {
     Holding,
     Input,
@@ -34,7 +53,11 @@ enum ModbusRegisterType {
 #[derive(Deserialize)]
 struct ModbusConfig {
     proto: ModbusProtocol,
+    #[serde(default = "get_default_pull_list")]
     pull: Vec<ModbusPull>,
+    // entries with absolute addresses, pulls are planned automatically
+    #[serde(default = "get_default_process_list")]
+    process: Vec<ModbusProcess>,
 }
 
 define_de_source!(DEFAULT_MODBUS_PORT);
@@ -43,6 +66,78 @@ fn get_default_unit() -> u8 {
     0
 }
 
+fn get_default_order() -> Option<String> {
+    None
+}
+
+fn get_default_baud_rate() -> u32 {
+    9600
+}
+
+fn get_default_data_bits() -> u8 {
+    8
+}
+
+fn get_default_parity() -> String {
+    "none".to_owned()
+}
+
+fn get_default_stop_bits() -> u8 {
+    1
+}
+
+fn get_default_frame_delay() -> Option<f64> {
+    None
+}
+
+fn get_default_max_gap() -> u32 {
+    0
+}
+
+fn get_default_holes() -> Vec<String> {
+    Vec::new()
+}
+
+fn get_default_on_error() -> String {
+    "fail".to_owned()
+}
+
+fn get_default_retries() -> u32 {
+    0
+}
+
+fn get_default_retry_delay() -> f64 {
+    0.1
+}
+
+fn get_default_backoff_max() -> f64 {
+    60.0
+}
+
+fn get_default_pipeline() -> usize {
+    1
+}
+
+fn get_default_command() -> Option<String> {
+    None
+}
+
+fn get_default_identify() -> Option<String> {
+    None
+}
+
+fn get_default_addressing() -> String {
+    "zero".to_owned()
+}
+
+fn get_default_pull_list() -> Vec<ModbusPull> {
+    Vec::new()
+}
+
+fn get_default_process_list() -> Vec<ModbusProcess> {
+    Vec::new()
+}
+
 #[derive(Deserialize)]
 struct ModbusProtocol {
     name: String,
@@ -50,15 +145,121 @@ struct ModbusProtocol {
     source: HostPort,
     #[serde(default = "get_default_unit")]
     unit: u8,
+    #[serde(alias = "byte-order", default = "get_default_order")]
+    byte_order: Option<String>,
+    #[serde(alias = "word-order", default = "get_default_order")]
+    word_order: Option<String>,
+    // serial port settings
+    #[serde(alias = "baud-rate", default = "get_default_baud_rate")]
+    baud_rate: u32,
+    #[serde(alias = "data-bits", default = "get_default_data_bits")]
+    data_bits: u8,
+    #[serde(default = "get_default_parity")]
+    parity: String,
+    #[serde(alias = "stop-bits", default = "get_default_stop_bits")]
+    stop_bits: u8,
+    // seconds, the silence between RTU frames (default is 3.5 chars)
+    #[serde(alias = "frame-delay", default = "get_default_frame_delay")]
+    frame_delay: Option<f64>,
+    // unused registers (coils) allowed inside an automatically planned pull
+    #[serde(alias = "max-gap", default = "get_default_max_gap")]
+    max_gap: u32,
+    // address ranges planned pulls never cross, e.g. h3010-h3019
+    #[serde(default = "get_default_holes")]
+    holes: Vec<String>,
+    // what to do if the device returns an exception: fail, skip or backoff
+    #[serde(alias = "on-error", default = "get_default_on_error")]
+    on_error: String,
+    // repeat the failed request N times before giving up
+    #[serde(default = "get_default_retries")]
+    retries: u32,
+    // seconds, pause before repeating the failed request
+    #[serde(alias = "retry-delay", default = "get_default_retry_delay")]
+    retry_delay: f64,
+    // seconds, max delay between the attempts for "backoff"
+    #[serde(alias = "backoff-max", default = "get_default_backoff_max")]
+    backoff_max: f64,
+    // max requests sent at once on one connection (Modbus/TCP only)
+    #[serde(default = "get_default_pipeline")]
+    pipeline: usize,
+    // write command channel: stdin, http://host:port or Unix socket path
+    #[serde(default = "get_default_command")]
+    command: Option<String>,
+    // set-id prefix for device identification events, outputted at startup
+    #[serde(default = "get_default_identify")]
+    identify: Option<String>,
+    // register addresses: zero (0-based), one (1-based) or modicon
+    #[serde(default = "get_default_addressing")]
+    addressing: String,
 }
 
 #[derive(Deserialize)]
 struct ModbusPull {
     reg: String,
+    #[serde(default = "get_default_pull_count")]
     count: u16,
     process: Vec<ModbusProcess>,
     #[serde(default = "get_default_unit")]
     unit: u8,
+    // override the default error policy
+    #[serde(alias = "on-error", default = "get_default_pull_on_error")]
+    on_error: Option<String>,
+    #[serde(default = "get_default_pull_retries")]
+    retries: Option<u32>,
+    #[serde(alias = "backoff-max", default = "get_default_pull_backoff_max")]
+    backoff_max: Option<f64>,
+    // read the FIFO queue (function 0x18), "reg" is the FIFO pointer address
+    #[serde(default = "get_default_pull_fifo")]
+    fifo: bool,
+    // write registers with the same request (function 0x17), e.g. handshakes
+    #[serde(default = "get_default_pull_write")]
+    write: Option<ModbusPullWrite>,
+}
+
+#[derive(Deserialize)]
+struct ModbusPullWrite {
+    reg: String,
+    values: Vec<u16>,
+}
+
+fn get_default_pull_count() -> u16 {
+    0
+}
+
+fn get_default_pull_fifo() -> bool {
+    false
+}
+
+fn get_default_pull_write() -> Option<ModbusPullWrite> {
+    None
+}
+
+fn get_default_pull_on_error() -> Option<String> {
+    None
+}
+
+fn get_default_pull_retries() -> Option<u32> {
+    None
+}
+
+fn get_default_pull_backoff_max() -> Option<f64> {
+    None
+}
+
+fn get_default_bits() -> Option<String> {
+    None
+}
+
+fn get_default_process_count() -> usize {
+    1
+}
+
+fn get_default_stride() -> Option<usize> {
+    None
+}
+
+fn get_default_start_index() -> usize {
+    0
 }
 
 fn get_default_type() -> String {
@@ -68,12 +269,346 @@ fn get_default_type() -> String {
 #[derive(Deserialize)]
 struct ModbusProcess {
     offset: String,
+    // bit range, Y-Z or Y..Z, same as offset X/Y..Z
+    #[serde(default = "get_default_bits")]
+    bits: Option<String>,
+    // array entries
+    #[serde(default = "get_default_process_count")]
+    count: usize,
+    #[serde(default = "get_default_stride")]
+    stride: Option<usize>,
+    #[serde(alias = "start-index", default = "get_default_start_index")]
+    start_index: usize,
     #[serde(alias = "type", default = "get_default_type")]
     r#type: String,
     #[serde(alias = "set-id")]
     set_id: String,
     #[serde(default = "datatypes::empty_transform_task")]
     transform: datatypes::EventTransformList,
+    #[serde(default = "alarm::empty_alarm_list")]
+    alarms: alarm::AlarmList,
+    // sentinel values, reported as null
+    #[serde(default = "datatypes::get_default_invalid_values")]
+    invalid: Vec<f64>,
+    #[serde(
+        alias = "on-invalid",
+        default = "datatypes::get_default_invalid_action",
+        deserialize_with = "datatypes::de_invalid_action"
+    )]
+    on_invalid: datatypes::InvalidAction,
+    // output "out-of-range" quality if the value (after transformations) is
+    // out of range
+    #[serde(default = "datatypes::get_default_range_min")]
+    min: Option<f64>,
+    #[serde(default = "datatypes::get_default_range_max")]
+    max: Option<f64>,
+    // seconds, output "stale" quality event if the value isn't changed
+    #[serde(alias = "stale-after", default = "datatypes::get_default_stale_after")]
+    stale_after: Option<f64>,
+    // seconds, repeat the stale event
+    #[serde(
+        alias = "stale-repeat",
+        default = "datatypes::get_default_stale_repeat"
+    )]
+    stale_repeat: Option<f64>,
+    #[serde(alias = "byte-order", default = "get_default_order")]
+    byte_order: Option<String>,
+    #[serde(alias = "word-order", default = "get_default_order")]
+    word_order: Option<String>,
+    // automatically planned entries only
+    #[serde(default = "get_default_unit")]
+    unit: u8,
+}
+
+// byte and word order of multi-register data, if word order isn't specified,
+// integers are decoded high-word-first and reals low-word-first
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct DataOrder {
+    word_swap: Option<bool>,
+    // big / little byte order sets the byte order inside the registers only
+    byte_swap: bool,
+}
+
+impl DataOrder {
+    pub(crate) fn with(self, byte_order: Option<&String>, word_order: Option<&String>) -> Self {
+        let mut order = self;
+        if let Some(v) = byte_order {
+            match v.to_lowercase().as_str() {
+                "big" | "be" => order.byte_swap = false,
+                "little" | "le" | "swap" => order.byte_swap = true,
+                "abcd" => order = DataOrder::from_swaps(false, false),
+                "cdab" => order = DataOrder::from_swaps(true, false),
+                "badc" => order = DataOrder::from_swaps(false, true),
+                "dcba" => order = DataOrder::from_swaps(true, true),
+                _ => panic!("invalid byte order: {}", v),
+            }
+        }
+        if let Some(v) = word_order {
+            order.word_swap = Some(match v.to_lowercase().as_str() {
+                "big" | "be" | "high" => false,
+                "little" | "le" | "low" | "swap" => true,
+                _ => panic!("invalid word order: {}", v),
+            });
+        }
+        order
+    }
+
+    fn from_swaps(word_swap: bool, byte_swap: bool) -> Self {
+        DataOrder {
+            word_swap: Some(word_swap),
+            byte_swap,
+        }
+    }
+
+    // strings are read register-by-register, word order is not used. For odd
+    // lengths the second byte of the last register is ignored
+    fn get_str(&self, v: &[u16], offset: usize, len: usize) -> String {
+        let count = len.div_ceil(2);
+        let regs = v.get(offset..offset + count).expect(ERROR_OOB);
+        let mut result = Vec::with_capacity(count * 2);
+        for r in regs {
+            match self.byte_swap {
+                true => result.extend_from_slice(&r.to_le_bytes()),
+                false => result.extend_from_slice(&r.to_be_bytes()),
+            }
+        }
+        result.truncate(len);
+        decode_str_data(&result)
+    }
+
+    // reverse to get_str, the string is padded with null chars
+    fn put_str(&self, value: &str, len: usize) -> Result<Vec<u16>, String> {
+        let mut data = value.as_bytes().to_vec();
+        if data.len() > len {
+            return Err(format!("string is longer than {} bytes", len));
+        }
+        data.resize(len.div_ceil(2) * 2, 0);
+        return Ok(data
+            .chunks(2)
+            .map(|c| match self.byte_swap {
+                true => u16::from_le_bytes([c[0], c[1]]),
+                false => u16::from_be_bytes([c[0], c[1]]),
+            })
+            .collect());
+    }
+
+    // reverse to get_bytes, big-endian (ABCD) bytes are put into registers
+    fn put_bytes(&self, b: &[u8], word_swap: bool) -> Vec<u16> {
+        let mut regs: Vec<u16> = b
+            .chunks(2)
+            .map(|c| match self.byte_swap {
+                true => u16::from_le_bytes([c[0], c[1]]),
+                false => u16::from_be_bytes([c[0], c[1]]),
+            })
+            .collect();
+        if self.word_swap.unwrap_or(word_swap) {
+            regs.reverse();
+        }
+        return regs;
+    }
+
+    // returns registers as big-endian (ABCD) bytes
+    fn get_bytes(&self, v: &[u16], offset: usize, count: usize, word_swap: bool) -> Vec<u8> {
+        let mut regs = v.get(offset..offset + count).expect(ERROR_OOB).to_vec();
+        if self.word_swap.unwrap_or(word_swap) {
+            regs.reverse();
+        }
+        let mut result = Vec::with_capacity(count * 2);
+        for r in regs {
+            match self.byte_swap {
+                true => result.extend_from_slice(&r.to_le_bytes()),
+                false => result.extend_from_slice(&r.to_be_bytes()),
+            }
+        }
+        result
+    }
+
+    fn get_real64(&self, v: &[u16], offset: usize) -> f64 {
+        let b = self.get_bytes(v, offset, 4, true);
+        return Ieee754::from_bits(u64::from_be_bytes([
+            b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+        ]));
+    }
+}
+
+fn parse_write_value<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    return value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| format!("invalid value: {}", value));
+}
+
+// registers are written as-is, hex values are allowed
+fn parse_register_value(value: &str) -> Result<u16, String> {
+    let v = value.trim();
+    return match v.starts_with("0x") {
+        true => u16::from_str_radix(&v[2..], 16).map_err(|_| format!("invalid value: {}", v)),
+        false => parse_write_value(v),
+    };
+}
+
+pub(crate) fn parse_coil_value(value: &str) -> Result<bool, String> {
+    return match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "on" => Ok(true),
+        "0" | "false" | "off" => Ok(false),
+        v => Err(format!("invalid value: {}", v)),
+    };
+}
+
+// encode the value into registers, reverse to decoding
+pub(crate) fn encode_value(
+    value: &str,
+    tp: GenDataType,
+    order: &DataOrder,
+) -> Result<Vec<u16>, String> {
+    return Ok(match tp {
+        GenDataType::Uint16 => {
+            order.put_bytes(&parse_write_value::<u16>(value)?.to_be_bytes(), false)
+        }
+        GenDataType::Int16 => {
+            order.put_bytes(&parse_write_value::<i16>(value)?.to_be_bytes(), false)
+        }
+        GenDataType::Uint32 => {
+            order.put_bytes(&parse_write_value::<u32>(value)?.to_be_bytes(), false)
+        }
+        GenDataType::Int32 => {
+            order.put_bytes(&parse_write_value::<i32>(value)?.to_be_bytes(), false)
+        }
+        GenDataType::Uint64 => {
+            order.put_bytes(&parse_write_value::<u64>(value)?.to_be_bytes(), false)
+        }
+        GenDataType::Int64 => {
+            order.put_bytes(&parse_write_value::<i64>(value)?.to_be_bytes(), false)
+        }
+        GenDataType::Real32 => {
+            order.put_bytes(&parse_write_value::<f32>(value)?.to_be_bytes(), true)
+        }
+        GenDataType::Real64 => {
+            order.put_bytes(&parse_write_value::<f64>(value)?.to_be_bytes(), true)
+        }
+        GenDataType::Bcd16 => {
+            let v = encode_bcd(parse_write_value(value)?, 4)
+                .ok_or(format!("value out of range: {}", value))?;
+            order.put_bytes(&(v as u16).to_be_bytes(), false)
+        }
+        GenDataType::Bcd32 => {
+            let v = encode_bcd(parse_write_value(value)?, 8)
+                .ok_or(format!("value out of range: {}", value))?;
+            order.put_bytes(&(v as u32).to_be_bytes(), false)
+        }
+        GenDataType::Fixed(decimals) => {
+            let v = encode_fixed(parse_write_value(value)?, decimals);
+            if v < i16::MIN as i64 || v > i16::MAX as i64 {
+                return Err(format!("value out of range: {}", value));
+            }
+            order.put_bytes(&(v as i16).to_be_bytes(), false)
+        }
+        GenDataType::Fixed32(decimals) => {
+            let v = encode_fixed(parse_write_value(value)?, decimals);
+            if v < i32::MIN as i64 || v > i32::MAX as i64 {
+                return Err(format!("value out of range: {}", value));
+            }
+            order.put_bytes(&(v as i32).to_be_bytes(), false)
+        }
+        GenDataType::Str(len) => order.put_str(value, len.unwrap() * 2)?,
+        GenDataType::StrBytes(len) => order.put_str(value, len)?,
+        _ => return Err(format!("{} can not be written", tp)),
+    });
+}
+
+// registers used by the data type, bit entries use 1 bit
+pub(crate) fn get_register_count(tp: GenDataType) -> usize {
+    match tp {
+        GenDataType::Uint32
+        | GenDataType::Int32
+        | GenDataType::Real32
+        | GenDataType::Bcd32
+        | GenDataType::Fixed32(_) => 2,
+        GenDataType::UnixTime32 => 2,
+        GenDataType::Uint64
+        | GenDataType::Int64
+        | GenDataType::Real64
+        | GenDataType::UnixTime64
+        | GenDataType::UnixTime64Ms => 4,
+        GenDataType::DateTime => 6,
+        GenDataType::Str(len) => len.unwrap_or(1),
+        GenDataType::StrBytes(len) => len.div_ceil(2),
+        _ => 1,
+    }
+}
+
+fn get_process_data_type(prc: &ModbusProcess, offset: &datatypes::DataOffset) -> GenDataType {
+    return match offset.bit {
+        Some(_) => match offset.bit_count {
+            Some(_) => GenDataType::BitField,
+            None => GenDataType::Bit,
+        },
+        None => match (prc.r#type.parse_data_type(), offset.half) {
+            (GenDataType::Uint16, Some(_)) => GenDataType::Uint8,
+            (GenDataType::Uint8, _) => GenDataType::Uint8,
+            (GenDataType::Int8, _) => GenDataType::Int8,
+            (GenDataType::Str(None), _) => {
+                panic!("string length not specified for {}", prc.set_id)
+            }
+            (tp, Some(_)) => panic!("{} can not be read from a register half", tp),
+            (tp, None) => tp,
+        },
+    };
+}
+
+// stride is set in bits for bit entries and in registers for others
+fn get_process_stride(
+    prc: &ModbusProcess,
+    offset: &datatypes::DataOffset,
+    tp: GenDataType,
+) -> usize {
+    return prc.stride.unwrap_or(match tp {
+        GenDataType::BitField => offset.bit_count.unwrap() as usize,
+        _ => get_register_count(tp),
+    });
+}
+
+// registers (coils) occupied by the entry, including array entries
+fn get_process_span(prc: &ModbusProcess, offset: &datatypes::DataOffset) -> usize {
+    let tp = get_process_data_type(prc, offset);
+    let stride = get_process_stride(prc, offset, tp);
+    return match offset.bit {
+        Some(bit) => {
+            let last = offset.offset * 16
+                + bit as usize
+                + (prc.count - 1) * stride
+                + offset.bit_count.unwrap_or(1) as usize
+                - 1;
+            last / 16 - offset.offset + 1
+        }
+        None => (prc.count - 1) * stride + get_register_count(tp),
+    };
+}
+
+// X(h|l) means high / low byte of Xth register
+fn parse_process_offset(offset: &str, addr: u32) -> datatypes::DataOffset {
+    let (o, suffix) = split_offset(offset);
+    let half = match suffix.chars().next() {
+        Some('h') | Some('H') => Some(datatypes::RegisterHalf::High),
+        Some('l') | Some('L') => Some(datatypes::RegisterHalf::Low),
+        _ => None,
+    };
+    let bits = match half {
+        Some(_) => &suffix[1..],
+        None => suffix,
+    };
+    let mut data_offset = format!("{}{}", o, bits).parse_data_offset(addr);
+    data_offset.half = half;
+    return data_offset;
+}
+
+// low byte is returned if register half isn't specified
+fn get_register_half(v: &[u16], offset: &datatypes::DataOffset) -> u8 {
+    let r = *v.get(offset.offset).expect(ERROR_OOB);
+    match offset.half {
+        Some(datatypes::RegisterHalf::High) => (r >> 8) as u8,
+        _ => r as u8,
+    }
 }
 
 // need to read coil data as Vec<u16>
@@ -114,6 +649,37 @@ struct ModbusDataProcessInfo {
     tp: datatypes::GenDataType,
     set_id: String,
     transform: datatypes::EventTransformList,
+    alarms: alarm::AlarmList,
+    checks: datatypes::ValueChecks,
+    order: DataOrder,
+}
+
+// process entry address, for write commands
+struct ModbusWriteTarget {
+    tp: ModbusRegisterType,
+    addr: u16,
+    unit: u8,
+    data_type: GenDataType,
+    order: DataOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorPolicy {
+    // stop the process
+    Fail,
+    // output the pull values as null
+    Skip,
+    // the same as skip, plus increase the delay between the attempts
+    Backoff,
+}
+
+fn parse_error_policy(policy: &str) -> ErrorPolicy {
+    return match policy.to_lowercase().as_str() {
+        "fail" => ErrorPolicy::Fail,
+        "skip" | "bad" | "mark-bad" => ErrorPolicy::Skip,
+        "backoff" | "back-off" => ErrorPolicy::Backoff,
+        _ => panic!("invalid error policy: {}", policy),
+    };
 }
 
 // TODO: move some fields to de_
@@ -124,6 +690,19 @@ struct ModbusPullData {
     addr: u16,
     count: u16,
     unit: u8,
+    on_error: ErrorPolicy,
+    retries: u32,
+    backoff_max: Duration,
+    function: PullFunction,
+}
+
+#[derive(Debug, PartialEq)]
+enum PullFunction {
+    Read,
+    // FIFO queue (0x18)
+    Fifo,
+    // read / write multiple registers (0x17), the write address and values
+    ReadWrite(u16, Vec<u16>),
 }
 
 define_task_result!(Vec<u16>);
@@ -132,23 +711,55 @@ define_task_result!(Vec<u16>);
 enum ErrorKind {
     ServerError,
     UdpBindError,
+    #[cfg(unix)]
+    SerialPortUnavailable,
 }
 
 struct TcpClient {
     stream: std::net::TcpStream,
     tr_id: u16,
+    timeout: Duration,
 }
 
 struct UdpClient {
     socket: std::net::UdpSocket,
     tr_id: u16,
     target: SocketAddr,
+    timeout: Duration,
+}
+
+// RTU / ASCII frames over a serial line or a raw TCP stream
+struct FramedClient {
+    link: FrameLink,
+    proto: ModbusProto,
+    timeout: Duration,
+    frame_delay: Duration,
+    last_frame: Instant,
+}
+
+enum FrameLink {
+    #[cfg(unix)]
+    Serial(SerialPort),
+    Tcp(TcpStream),
 }
 
 macro_rules! prepare_mreq {
-    ($unit_id:expr) => {
-        ModbusRequest::new($unit_id, ModbusProto::TcpUdp);
-    };
+    ($client:expr, $unit_id:expr) => {{
+        let mut mreq = ModbusRequest::new(
+            $unit_id,
+            match $client {
+                ModbusClient::Framed(c) => c.proto,
+                _ => ModbusProto::TcpUdp,
+            },
+        );
+        // responses are validated with the transaction ID
+        match $client {
+            ModbusClient::Tcp(c) => mreq.tr_id = c.tr_id,
+            ModbusClient::Udp(c) => mreq.tr_id = c.tr_id,
+            ModbusClient::Framed(_) => {}
+        }
+        mreq
+    }};
 }
 
 macro_rules! incr_tr_id {
@@ -160,21 +771,103 @@ macro_rules! incr_tr_id {
     };
 }
 
+fn connect_tcp(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, ErrorKind> {
+    let addr: SocketAddr = format!("{}:{}", host, port).parse().unwrap();
+    let stream = TcpStream::connect_timeout(&addr, timeout);
+    stream.map_or_else(
+        |_| Err(ErrorKind::ServerError),
+        |s| {
+            s.set_read_timeout(Some(timeout)).unwrap();
+            s.set_write_timeout(Some(timeout)).unwrap();
+            Ok(s)
+        },
+    )
+}
+
 impl TcpClient {
     fn new(host: &str, port: u16, timeout: Duration) -> Result<Self, ErrorKind> {
-        let addr: SocketAddr = format!("{}:{}", host, port).parse().unwrap();
-        let stream = TcpStream::connect_timeout(&addr, timeout);
-        stream.map_or_else(
-            |_| Err(ErrorKind::ServerError),
-            |s| {
-                s.set_read_timeout(Some(timeout)).unwrap();
-                s.set_write_timeout(Some(timeout)).unwrap();
-                Ok(Self {
-                    stream: s,
-                    tr_id: 1,
-                })
-            },
-        )
+        Ok(Self {
+            stream: connect_tcp(host, port, timeout)?,
+            tr_id: 1,
+            timeout,
+        })
+    }
+
+    // discard late responses of the timed out requests
+    fn drain(&mut self) -> Result<(), rmodbus::ErrorKind> {
+        if self.stream.set_nonblocking(true).is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        let mut buf = [0u8; 256];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Err(rmodbus::ErrorKind::CommunicationError),
+                Ok(_) => {}
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => break Ok(()),
+                Err(_) => break Err(rmodbus::ErrorKind::CommunicationError),
+            }
+        };
+        if self.stream.set_nonblocking(false).is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        return result;
+    }
+
+    // MBAP header and PDU
+    fn read_frame(&mut self) -> Result<Vec<u8>, rmodbus::ErrorKind> {
+        let mut frame = vec![0u8; 6];
+        if self.stream.read_exact(&mut frame).is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        let proto = u16::from_be_bytes([frame[2], frame[3]]);
+        let len = u16::from_be_bytes([frame[4], frame[5]]) as usize;
+        if proto != 0 || !(2..=254).contains(&len) {
+            return Err(rmodbus::ErrorKind::FrameBroken);
+        }
+        frame.resize(6 + len, 0);
+        if self.stream.read_exact(&mut frame[6..]).is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        return Ok(frame);
+    }
+
+    // send the requests at once and collect the responses by transaction ID,
+    // frames with unknown IDs (e.g. late responses) are skipped
+    fn process_requests(
+        &mut self,
+        requests: &[Vec<u8>],
+    ) -> Vec<Result<Vec<u8>, rmodbus::ErrorKind>> {
+        let mut results: Vec<Option<Result<Vec<u8>, rmodbus::ErrorKind>>> =
+            requests.iter().map(|_| None).collect();
+        if self.drain().is_ok() && self.stream.write_all(&requests.concat()).is_ok() {
+            let deadline = Instant::now() + self.timeout;
+            let mut pending = requests.len();
+            while pending > 0 {
+                // the whole batch must fit into the timeout
+                let t = Instant::now();
+                if t >= deadline || self.stream.set_read_timeout(Some(deadline - t)).is_err() {
+                    break;
+                }
+                let frame = match self.read_frame() {
+                    Ok(v) => v,
+                    Err(_) => break,
+                };
+                if let Some(i) = requests.iter().position(|r| r[..2] == frame[..2]) {
+                    if results[i].is_none() {
+                        // the unit ID must match as well
+                        results[i] = Some(match frame[6] == requests[i][6] {
+                            true => Ok(frame),
+                            false => Err(rmodbus::ErrorKind::FrameBroken),
+                        });
+                        pending -= 1;
+                    }
+                }
+            }
+        }
+        return results
+            .into_iter()
+            .map(|r| r.unwrap_or(Err(rmodbus::ErrorKind::CommunicationError)))
+            .collect();
     }
 }
 
@@ -198,94 +891,342 @@ impl UdpClient {
                     socket: s,
                     tr_id: 1,
                     target,
+                    timeout,
                 })
             },
         )
     }
 }
 
-trait ModbusNetworkClient {
-    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind>;
-    fn prepare(&mut self);
+#[cfg(unix)]
+fn open_serial(proto: &ModbusProtocol) -> Result<SerialPort, ErrorKind> {
+    return SerialPort::open(
+        &proto.source.host,
+        proto.baud_rate,
+        proto.data_bits,
+        &proto.parity,
+        proto.stop_bits,
+    )
+    .map_err(|_| ErrorKind::SerialPortUnavailable);
 }
 
-impl ModbusNetworkClient for TcpClient {
-    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind> {
-        if self.stream.write(request).is_err() {
-            return Err(rmodbus::ErrorKind::CommunicationError);
-        }
-        let mut buf = [0u8; 6];
-        if self.stream.read_exact(&mut buf).is_err() {
-            return Err(rmodbus::ErrorKind::CommunicationError);
-        }
-        let mut response = Vec::new();
-        response.extend_from_slice(&buf);
-        let len = guess_response_frame_len(&buf, ModbusProto::TcpUdp).unwrap();
-        if len > 6 {
-            let mut rest = vec![0u8; (len - 6) as usize];
-            if self.stream.read_exact(&mut rest).is_err() {
-                return Err(rmodbus::ErrorKind::CommunicationError);
+impl FrameLink {
+    // drop late replies to the previous requests
+    fn clear_input(&mut self) -> Result<(), std::io::Error> {
+        match self {
+            #[cfg(unix)]
+            FrameLink::Serial(port) => port.clear_input(),
+            FrameLink::Tcp(stream) => {
+                stream.set_nonblocking(true)?;
+                let mut buf = [0u8; 256];
+                while let Ok(n) = stream.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                }
+                stream.set_nonblocking(false)?;
             }
-            response.extend(rest);
         }
-        Ok(response)
-    }
-    fn prepare(&mut self) {
-        incr_tr_id!(self);
+        return Ok(());
     }
-}
 
-impl ModbusNetworkClient for UdpClient {
-    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind> {
-        let len = match self.socket.send_to(request, self.target) {
-            Ok(v) => v,
-            Err(_) => return Err(rmodbus::ErrorKind::CommunicationError),
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), std::io::Error> {
+        return match self {
+            #[cfg(unix)]
+            FrameLink::Serial(port) => port.write_all(buf),
+            FrameLink::Tcp(stream) => stream.write_all(buf),
         };
-        if len != request.len() {
-            return Err(rmodbus::ErrorKind::CommunicationError);
-        }
-        let mut buf: ModbusFrameBuf = [0; 256];
-        let (n, _addr) = match self.socket.recv_from(&mut buf) {
-            Ok(v) => v,
-            Err(_) => {
-                return Err(rmodbus::ErrorKind::CommunicationError);
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8], deadline: Instant) -> Result<(), std::io::Error> {
+        return match self {
+            #[cfg(unix)]
+            FrameLink::Serial(port) => port.read_exact(buf, deadline),
+            FrameLink::Tcp(stream) => {
+                let t = Instant::now();
+                if t >= deadline {
+                    return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+                }
+                stream.set_read_timeout(Some(deadline - t))?;
+                stream.read_exact(buf)
             }
         };
-        let mut response = Vec::new();
-        response.extend_from_slice(&buf[..n]);
-        Ok(response)
-    }
-    fn prepare(&mut self) {
-        incr_tr_id!(self);
     }
 }
 
-macro_rules! prepare_client {
-    ($client:expr) => {
-        match $client {
-            ModbusClient::Tcp(c) => c.prepare(),
-            ModbusClient::Udp(c) => c.prepare(),
+impl FramedClient {
+    fn new(proto: &ModbusProtocol, timeout: Duration) -> Result<Self, ErrorKind> {
+        let (link, mproto) = match proto.name.as_str() {
+            "modbus/rtu-tcp" => (
+                FrameLink::Tcp(connect_tcp(&proto.source.host, proto.source.port, timeout)?),
+                ModbusProto::Rtu,
+            ),
+            "modbus/ascii-tcp" => (
+                FrameLink::Tcp(connect_tcp(&proto.source.host, proto.source.port, timeout)?),
+                ModbusProto::Ascii,
+            ),
+            #[cfg(unix)]
+            "modbus/rtu" => (FrameLink::Serial(open_serial(proto)?), ModbusProto::Rtu),
+            #[cfg(unix)]
+            "modbus/ascii" => (FrameLink::Serial(open_serial(proto)?), ModbusProto::Ascii),
+            _ => {
+                unimplemented!("protocol {}", proto.name);
+            }
         };
-    };
-}
+        // ASCII frames have start and end chars, RTU frames are separated with
+        // the silence
+        let frame_delay = match (proto.frame_delay, &link, mproto) {
+            (Some(v), _, _) => Duration::from_secs_f64(v),
+            #[cfg(unix)]
+            (None, FrameLink::Serial(port), ModbusProto::Rtu) => port.frame_delay(),
+            _ => Duration::from_secs(0),
+        };
+        Ok(Self {
+            link,
+            proto: mproto,
+            timeout,
+            frame_delay,
+            last_frame: Instant::now(),
+        })
+    }
 
-macro_rules! process_request {
-    ($client:expr, $request:expr) => {
-        match $client {
-            ModbusClient::Tcp(c) => c.process_request($request),
-            ModbusClient::Udp(c) => c.process_request($request),
+    fn read_response(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind> {
+        if self.link.clear_input().is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
         }
-    };
-}
-
-fn read_coils(
-    client: &mut ModbusClient,
+        let sent = match self.proto {
+            ModbusProto::Ascii => {
+                let mut frame = Vec::new();
+                generate_ascii_frame(request, &mut frame)?;
+                self.link.write_all(&frame)
+            }
+            _ => self.link.write_all(request),
+        };
+        if sent.is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        let deadline = Instant::now() + self.timeout;
+        // RTU: unit, function and byte count / exception code
+        // ASCII: the same, hex-encoded, with the leading colon
+        let head_len = match self.proto {
+            ModbusProto::Ascii => 7,
+            _ => 3,
+        };
+        let mut response = vec![0u8; head_len];
+        if self.link.read_exact(&mut response, deadline).is_err() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        let func = match self.proto {
+            ModbusProto::Ascii => std::str::from_utf8(&response[3..5])
+                .ok()
+                .and_then(|v| u8::from_str_radix(v, 16).ok())
+                .unwrap_or(0),
+            _ => response[1],
+        };
+        match (is_extended_function(func), self.proto) {
+            // ASCII frames are read till the line end
+            (true, ModbusProto::Ascii) => {
+                while response.last() != Some(&b'\n') {
+                    if response.len() > 513 {
+                        return Err(rmodbus::ErrorKind::FrameBroken);
+                    }
+                    let mut b = [0u8; 1];
+                    if self.link.read_exact(&mut b, deadline).is_err() {
+                        return Err(rmodbus::ErrorKind::CommunicationError);
+                    }
+                    response.push(b[0]);
+                }
+            }
+            // the length of RTU frames is known only after parsing the head
+            (true, _) => loop {
+                let len = get_extended_frame_len(&response) + 2;
+                if len <= response.len() {
+                    break;
+                }
+                if len > 256 {
+                    return Err(rmodbus::ErrorKind::FrameBroken);
+                }
+                let mut rest = vec![0u8; len - response.len()];
+                if self.link.read_exact(&mut rest, deadline).is_err() {
+                    return Err(rmodbus::ErrorKind::CommunicationError);
+                }
+                response.extend(rest);
+            },
+            (false, _) => {
+                let len = get_response_frame_len(&response, self.proto)?;
+                if len > head_len {
+                    let mut rest = vec![0u8; len - head_len];
+                    if self.link.read_exact(&mut rest, deadline).is_err() {
+                        return Err(rmodbus::ErrorKind::CommunicationError);
+                    }
+                    response.extend(rest);
+                }
+            }
+        }
+        return match self.proto {
+            ModbusProto::Ascii => {
+                let mut buf: ModbusFrameBuf = [0; 256];
+                let n = parse_ascii_frame(&response, response.len(), &mut buf, 0)?;
+                Ok(buf[..n as usize].to_vec())
+            }
+            _ => Ok(response),
+        };
+    }
+}
+
+// full frame length of the standard function response, by the frame head (the
+// usize math, ASCII frames of long responses don't fit into u8)
+fn get_response_frame_len(head: &[u8], proto: ModbusProto) -> Result<usize, rmodbus::ErrorKind> {
+    let (f, multiplier, extra) = match proto {
+        ModbusProto::Ascii => {
+            let mut f = [0u8; 3];
+            for (i, b) in f.iter_mut().enumerate() {
+                *b = std::str::from_utf8(&head[1 + i * 2..3 + i * 2])
+                    .ok()
+                    .and_then(|v| u8::from_str_radix(v, 16).ok())
+                    .ok_or(rmodbus::ErrorKind::FrameBroken)?;
+            }
+            // colon, LRC and CR LF
+            (f, 2, 5)
+        }
+        // CRC16
+        _ => ([head[0], head[1], head[2]], 1, 2),
+    };
+    return match f[1] {
+        1..=4 => Ok((f[2] as usize + 3) * multiplier + extra),
+        5 | 6 | 15 | 16 => Ok(6 * multiplier + extra),
+        func if func >= 0x80 => Ok(3 * multiplier + extra),
+        _ => Err(rmodbus::ErrorKind::FrameBroken),
+    };
+}
+
+fn is_extended_function(func: u8) -> bool {
+    return func == MODBUS_READ_WRITE_HOLDINGS
+        || func == MODBUS_READ_FIFO
+        || func == MODBUS_ENCAPSULATED;
+}
+
+// RTU frame length (without CRC) of the extended function response, as far as
+// it can be determined from the bytes already read
+fn get_extended_frame_len(f: &[u8]) -> usize {
+    return match f[1] {
+        MODBUS_READ_WRITE_HOLDINGS => 3 + f[2] as usize,
+        MODBUS_READ_FIFO => match f.len() {
+            n if n < 4 => 4,
+            _ => 4 + u16::from_be_bytes([f[2], f[3]]) as usize,
+        },
+        MODBUS_ENCAPSULATED => {
+            // MEI type, read code, conformity, more follows, next object,
+            // object count, then objects: id, length and value
+            if f.len() < 8 {
+                return 8;
+            }
+            let mut pos = 8;
+            for _ in 0..f[7] {
+                if f.len() < pos + 2 {
+                    return pos + 2;
+                }
+                pos += 2 + f[pos + 1] as usize;
+            }
+            pos
+        }
+        _ => 3,
+    };
+}
+
+trait ModbusNetworkClient {
+    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind>;
+    fn prepare(&mut self);
+}
+
+impl ModbusNetworkClient for TcpClient {
+    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind> {
+        return self.process_requests(&[request.to_vec()]).pop().unwrap();
+    }
+    fn prepare(&mut self) {
+        incr_tr_id!(self);
+    }
+}
+
+impl ModbusNetworkClient for FramedClient {
+    // CRC / LRC is checked by the response parser
+    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind> {
+        // keep the silence between frames
+        let next_frame = self.last_frame + self.frame_delay;
+        let t = Instant::now();
+        if next_frame > t {
+            thread::sleep(next_frame - t);
+        }
+        let result = self.read_response(request);
+        self.last_frame = Instant::now();
+        result
+    }
+    fn prepare(&mut self) {}
+}
+
+impl ModbusNetworkClient for UdpClient {
+    fn process_request(&mut self, request: &[u8]) -> Result<Vec<u8>, rmodbus::ErrorKind> {
+        let len = match self.socket.send_to(request, self.target) {
+            Ok(v) => v,
+            Err(_) => return Err(rmodbus::ErrorKind::CommunicationError),
+        };
+        if len != request.len() {
+            return Err(rmodbus::ErrorKind::CommunicationError);
+        }
+        // datagrams of the previous requests are skipped
+        let deadline = Instant::now() + self.timeout;
+        let mut buf: ModbusFrameBuf = [0; 256];
+        while Instant::now() < deadline {
+            let (n, _addr) = match self.socket.recv_from(&mut buf) {
+                Ok(v) => v,
+                Err(_) => {
+                    return Err(rmodbus::ErrorKind::CommunicationError);
+                }
+            };
+            if n > 7 && buf[..2] == request[..2] {
+                if buf[6] != request[6] {
+                    return Err(rmodbus::ErrorKind::FrameBroken);
+                }
+                let mut response = Vec::new();
+                response.extend_from_slice(&buf[..n]);
+                return Ok(response);
+            }
+        }
+        Err(rmodbus::ErrorKind::CommunicationError)
+    }
+    fn prepare(&mut self) {
+        incr_tr_id!(self);
+    }
+}
+
+macro_rules! prepare_client {
+    ($client:expr) => {
+        match $client {
+            ModbusClient::Tcp(c) => c.prepare(),
+            ModbusClient::Udp(c) => c.prepare(),
+            ModbusClient::Framed(c) => c.prepare(),
+        };
+    };
+}
+
+macro_rules! process_request {
+    ($client:expr, $request:expr) => {
+        match $client {
+            ModbusClient::Tcp(c) => c.process_request($request),
+            ModbusClient::Udp(c) => c.process_request($request),
+            ModbusClient::Framed(c) => c.process_request($request),
+        }
+    };
+}
+
+fn read_coils(
+    client: &mut ModbusClient,
     unit_id: u8,
     reg: u16,
     count: u16,
 ) -> Result<Vec<u16>, rmodbus::ErrorKind> {
     prepare_client!(client);
-    let mut mreq = prepare_mreq!(unit_id);
+    let mut mreq = prepare_mreq!(client, unit_id);
     let mut request = Vec::new();
     match mreq.generate_get_coils(reg, count, &mut request) {
         Ok(_) => {}
@@ -300,7 +1241,7 @@ fn read_coils(
     let mut result = Vec::new();
     match mreq.parse_bool_as_u16(&response, &mut result) {
         Ok(_) => Ok(result),
-        Err(e) => Err(e),
+        Err(e) => Err(get_error_kind(e, &mreq, &response)),
     }
 }
 fn read_discretes(
@@ -310,7 +1251,7 @@ fn read_discretes(
     count: u16,
 ) -> Result<Vec<u16>, rmodbus::ErrorKind> {
     prepare_client!(client);
-    let mut mreq = prepare_mreq!(unit_id);
+    let mut mreq = prepare_mreq!(client, unit_id);
     let mut request = Vec::new();
     match mreq.generate_get_discretes(reg, count, &mut request) {
         Ok(_) => {}
@@ -323,65 +1264,975 @@ fn read_discretes(
         }
     };
     let mut result = Vec::new();
-    match mreq.parse_bool_as_u16(&response, &mut result) {
-        Ok(_) => Ok(result),
-        Err(e) => Err(e),
+    match mreq.parse_bool_as_u16(&response, &mut result) {
+        Ok(_) => Ok(result),
+        Err(e) => Err(get_error_kind(e, &mreq, &response)),
+    }
+}
+fn read_inputs(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    reg: u16,
+    count: u16,
+) -> Result<Vec<u16>, rmodbus::ErrorKind> {
+    prepare_client!(client);
+    let mut mreq = prepare_mreq!(client, unit_id);
+    let mut request = Vec::new();
+    match mreq.generate_get_inputs(reg, count, &mut request) {
+        Ok(_) => {}
+        Err(e) => return Err(e),
+    }
+    let response = match process_request!(client, &request) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    let mut result = Vec::new();
+    match mreq.parse_u16(&response, &mut result) {
+        Ok(_) => Ok(result),
+        Err(e) => Err(get_error_kind(e, &mreq, &response)),
+    }
+}
+// rmodbus maps gateway exception codes improperly, get them from the frame
+fn get_error_kind(
+    err: rmodbus::ErrorKind,
+    mreq: &ModbusRequest,
+    response: &[u8],
+) -> rmodbus::ErrorKind {
+    let pos = match mreq.proto {
+        ModbusProto::TcpUdp => 7,
+        _ => 1,
+    };
+    return match (response.get(pos), response.get(pos + 1)) {
+        (Some(func), Some(code)) if *func == mreq.func | 0x80 => match code {
+            0x0a => rmodbus::ErrorKind::GatewayPathUnavailable,
+            0x0b => rmodbus::ErrorKind::GatewayTargetFailed,
+            0x09 | 0x10 => rmodbus::ErrorKind::UnknownError,
+            _ => rmodbus::ErrorKind::from_modbus_error(*code),
+        },
+        _ => err,
+    };
+}
+
+// Modbus exception code, returned by the device
+fn get_exception_code(err: &rmodbus::ErrorKind) -> Option<u8> {
+    return match err {
+        rmodbus::ErrorKind::IllegalFunction => Some(0x01),
+        rmodbus::ErrorKind::IllegalDataAddress => Some(0x02),
+        rmodbus::ErrorKind::IllegalDataValue => Some(0x03),
+        rmodbus::ErrorKind::SlaveDeviceFailure => Some(0x04),
+        rmodbus::ErrorKind::Acknowledge => Some(0x05),
+        rmodbus::ErrorKind::SlaveDeviceBusy => Some(0x06),
+        rmodbus::ErrorKind::NegativeAcknowledge => Some(0x07),
+        rmodbus::ErrorKind::MemoryParityError => Some(0x08),
+        rmodbus::ErrorKind::GatewayPathUnavailable => Some(0x0a),
+        rmodbus::ErrorKind::GatewayTargetFailed => Some(0x0b),
+        _ => None,
+    };
+}
+
+fn is_comm_error(err: &rmodbus::ErrorKind) -> bool {
+    return *err == rmodbus::ErrorKind::CommunicationError
+        || *err == rmodbus::ErrorKind::FrameBroken
+        || *err == rmodbus::ErrorKind::FrameCRCError;
+}
+
+// the next attempt time of the failed pull, the delay is doubled after each
+// failed attempt
+fn get_backoff(
+    prev: Option<(Instant, Duration)>,
+    interval: Duration,
+    p: &ModbusPullData,
+    verbose: bool,
+) -> (Instant, Duration) {
+    let delay = match prev {
+        Some((_, d)) => d * 2,
+        None => interval,
+    }
+    .min(p.backoff_max);
+    if verbose {
+        pl::print_debug(&format!("{} next attempt in {:?}", p.label, delay));
+    }
+    return (Instant::now() + delay, delay);
+}
+
+fn write_coils(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    reg: u16,
+    values: &[bool],
+) -> Result<(), rmodbus::ErrorKind> {
+    prepare_client!(client);
+    let mut mreq = prepare_mreq!(client, unit_id);
+    let mut request = Vec::new();
+    match match values.len() {
+        1 => mreq.generate_set_coil(reg, values[0], &mut request),
+        _ => mreq.generate_set_coils_bulk(reg, values, &mut request),
+    } {
+        Ok(_) => {}
+        Err(e) => return Err(e),
+    }
+    let response = match process_request!(client, &request) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    return mreq
+        .parse_ok(&response)
+        .map_err(|e| get_error_kind(e, &mreq, &response));
+}
+
+fn write_holdings(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    reg: u16,
+    values: &[u16],
+) -> Result<(), rmodbus::ErrorKind> {
+    prepare_client!(client);
+    let mut mreq = prepare_mreq!(client, unit_id);
+    let mut request = Vec::new();
+    match match values.len() {
+        1 => mreq.generate_set_holding(reg, values[0], &mut request),
+        _ => mreq.generate_set_holdings_bulk(reg, values, &mut request),
+    } {
+        Ok(_) => {}
+        Err(e) => return Err(e),
+    }
+    let response = match process_request!(client, &request) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    return mreq
+        .parse_ok(&response)
+        .map_err(|e| get_error_kind(e, &mreq, &response));
+}
+
+// pipelined reads, the results are returned in the same order
+fn read_pipelined(
+    client: &mut TcpClient,
+    pulls: &[&ModbusPullData],
+) -> Vec<Result<Vec<u16>, rmodbus::ErrorKind>> {
+    let mut mreqs = Vec::new();
+    let mut requests = Vec::new();
+    for p in pulls {
+        client.prepare();
+        let mut mreq = ModbusRequest::new(p.unit, ModbusProto::TcpUdp);
+        mreq.tr_id = client.tr_id;
+        let mut request = Vec::new();
+        match p.tp {
+            ModbusRegisterType::Holding => {
+                mreq.generate_get_holdings(p.addr, p.count, &mut request)
+            }
+            ModbusRegisterType::Input => mreq.generate_get_inputs(p.addr, p.count, &mut request),
+            ModbusRegisterType::Coil => mreq.generate_get_coils(p.addr, p.count, &mut request),
+            ModbusRegisterType::Discrete => {
+                mreq.generate_get_discretes(p.addr, p.count, &mut request)
+            }
+        }
+        .expect("unable to generate Modbus request");
+        mreqs.push(mreq);
+        requests.push(request);
+    }
+    let responses = client.process_requests(&requests);
+    return mreqs
+        .iter_mut()
+        .zip(pulls)
+        .zip(responses)
+        .map(|((mreq, p), response)| {
+            let response = response?;
+            let mut result = Vec::new();
+            match p.tp {
+                ModbusRegisterType::Holding | ModbusRegisterType::Input => {
+                    mreq.parse_u16(&response, &mut result)
+                }
+                _ => mreq.parse_bool_as_u16(&response, &mut result),
+            }
+            .map_err(|e| get_error_kind(e, mreq, &response))?;
+            Ok(result)
+        })
+        .collect();
+}
+
+fn read_holdings(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    reg: u16,
+    count: u16,
+) -> Result<Vec<u16>, rmodbus::ErrorKind> {
+    prepare_client!(client);
+    let mut mreq = prepare_mreq!(client, unit_id);
+    let mut request = Vec::new();
+    match mreq.generate_get_holdings(reg, count, &mut request) {
+        Ok(_) => {}
+        Err(e) => return Err(e),
+    }
+    let response = match process_request!(client, &request) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(e);
+        }
+    };
+    let mut result = Vec::new();
+    match mreq.parse_u16(&response, &mut result) {
+        Ok(_) => Ok(result),
+        Err(e) => Err(get_error_kind(e, &mreq, &response)),
+    }
+}
+
+fn calc_crc16(frame: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for b in frame {
+        crc ^= *b as u16;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ 0xa001,
+            };
+        }
+    }
+    return crc;
+}
+
+fn calc_lrc(frame: &[u8]) -> u8 {
+    return frame.iter().fold(0u8, |lrc, b| lrc.wrapping_sub(*b));
+}
+
+// rmodbus doesn't generate requests for the extended functions, the PDU
+// (function code and data) is framed manually
+fn generate_extended_request(mreq: &ModbusRequest, pdu: &[u8]) -> Vec<u8> {
+    let mut request = Vec::new();
+    if mreq.proto == ModbusProto::TcpUdp {
+        request.extend_from_slice(&mreq.tr_id.to_be_bytes());
+        request.extend_from_slice(&[0, 0]);
+        request.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+    }
+    request.push(mreq.unit_id);
+    request.extend_from_slice(pdu);
+    match mreq.proto {
+        ModbusProto::Rtu => {
+            let crc = calc_crc16(&request);
+            request.extend_from_slice(&crc.to_le_bytes());
+        }
+        ModbusProto::Ascii => request.push(calc_lrc(&request)),
+        ModbusProto::TcpUdp => {}
+    }
+    return request;
+}
+
+// returns the response data after the function code
+fn process_extended(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    pdu: &[u8],
+) -> Result<Vec<u8>, rmodbus::ErrorKind> {
+    prepare_client!(client);
+    let mut mreq = prepare_mreq!(client, unit_id);
+    mreq.func = pdu[0];
+    let request = generate_extended_request(&mreq, pdu);
+    let response = process_request!(client, &request)?;
+    // checks the frame and the exception code
+    mreq.parse_ok(&response)
+        .map_err(|e| get_error_kind(e, &mreq, &response))?;
+    let (start, end) = match mreq.proto {
+        ModbusProto::TcpUdp => (8, response.len()),
+        ModbusProto::Rtu => (2, response.len() - 2),
+        ModbusProto::Ascii => (2, response.len() - 1),
+    };
+    return Ok(response[start..end].to_vec());
+}
+
+// the value count and the queued values, padded to FIFO_PULL_SIZE
+fn read_fifo(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    reg: u16,
+) -> Result<Vec<u16>, rmodbus::ErrorKind> {
+    let mut pdu = vec![MODBUS_READ_FIFO];
+    pdu.extend_from_slice(&reg.to_be_bytes());
+    let data = process_extended(client, unit_id, &pdu)?;
+    // byte count, FIFO count, values
+    if data.len() < 4 {
+        return Err(rmodbus::ErrorKind::FrameBroken);
+    }
+    let count = u16::from_be_bytes([data[2], data[3]]);
+    if count >= FIFO_PULL_SIZE || data.len() < 4 + count as usize * 2 {
+        return Err(rmodbus::ErrorKind::FrameBroken);
+    }
+    let mut result = vec![count];
+    for c in data[4..4 + count as usize * 2].chunks(2) {
+        result.push(u16::from_be_bytes([c[0], c[1]]));
+    }
+    result.resize(FIFO_PULL_SIZE as usize, 0);
+    return Ok(result);
+}
+
+// the registers are written before reading
+fn read_write_holdings(
+    client: &mut ModbusClient,
+    unit_id: u8,
+    reg: u16,
+    count: u16,
+    write_reg: u16,
+    values: &[u16],
+) -> Result<Vec<u16>, rmodbus::ErrorKind> {
+    let mut pdu = vec![MODBUS_READ_WRITE_HOLDINGS];
+    pdu.extend_from_slice(&reg.to_be_bytes());
+    pdu.extend_from_slice(&count.to_be_bytes());
+    pdu.extend_from_slice(&write_reg.to_be_bytes());
+    pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+    pdu.push((values.len() * 2) as u8);
+    for v in values {
+        pdu.extend_from_slice(&v.to_be_bytes());
+    }
+    let data = process_extended(client, unit_id, &pdu)?;
+    let len = count as usize * 2;
+    if data.is_empty() || data[0] as usize != len || data.len() < len + 1 {
+        return Err(rmodbus::ErrorKind::FrameBroken);
+    }
+    return Ok(data[1..len + 1]
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect());
+}
+
+// basic device identification objects: object ID and value
+fn read_device_id(
+    client: &mut ModbusClient,
+    unit_id: u8,
+) -> Result<Vec<(u8, String)>, rmodbus::ErrorKind> {
+    let mut objects = Vec::new();
+    let mut object_id = 0;
+    loop {
+        let data = process_extended(
+            client,
+            unit_id,
+            &[MODBUS_ENCAPSULATED, MEI_READ_DEVICE_ID, 0x01, object_id],
+        )?;
+        // MEI type, read code, conformity, more follows, next object, count
+        if data.len() < 6 || data[0] != MEI_READ_DEVICE_ID {
+            return Err(rmodbus::ErrorKind::FrameBroken);
+        }
+        let mut pos = 6;
+        for _ in 0..data[5] {
+            let len = *data.get(pos + 1).ok_or(rmodbus::ErrorKind::FrameBroken)? as usize;
+            let value = data
+                .get(pos + 2..pos + 2 + len)
+                .ok_or(rmodbus::ErrorKind::FrameBroken)?;
+            objects.push((data[pos], decode_str_data(value)));
+            pos += 2 + len;
+        }
+        // the device may split the objects into several responses
+        if data[3] != 0xff || data[4] <= object_id {
+            break;
+        }
+        object_id = data[4];
+    }
+    return Ok(objects);
+}
+
+fn get_device_object_name(object_id: u8) -> String {
+    return match object_id {
+        0x00 => "vendor",
+        0x01 => "product",
+        0x02 => "revision",
+        0x03 => "vendor-url",
+        0x04 => "product-name",
+        0x05 => "model",
+        0x06 => "app-name",
+        _ => return format!("object-{}", object_id),
+    }
+    .to_owned();
+}
+
+fn read_pull(
+    client: &mut ModbusClient,
+    p: &ModbusPullData,
+) -> Result<Vec<u16>, rmodbus::ErrorKind> {
+    return match p.function {
+        PullFunction::Fifo => read_fifo(client, p.unit, p.addr),
+        PullFunction::ReadWrite(write_reg, ref values) => {
+            read_write_holdings(client, p.unit, p.addr, p.count, write_reg, values)
+        }
+        PullFunction::Read => match p.tp {
+            ModbusRegisterType::Holding => read_holdings(client, p.unit, p.addr, p.count),
+            ModbusRegisterType::Input => read_inputs(client, p.unit, p.addr, p.count),
+            ModbusRegisterType::Coil => read_coils(client, p.unit, p.addr, p.count),
+            ModbusRegisterType::Discrete => read_discretes(client, p.unit, p.addr, p.count),
+        },
+    };
+}
+
+enum ModbusClient {
+    Tcp(TcpClient),
+    Udp(UdpClient),
+    Framed(FramedClient),
+}
+
+fn connect(proto: &ModbusProtocol, timeout: Duration) -> Result<ModbusClient, ErrorKind> {
+    return Ok(match proto.name.as_str() {
+        "modbus/tcp" => ModbusClient::Tcp(TcpClient::new(
+            &proto.source.host,
+            proto.source.port,
+            timeout,
+        )?),
+        "modbus/udp" => ModbusClient::Udp(UdpClient::new(
+            &proto.source.host,
+            proto.source.port,
+            timeout,
+        )?),
+        "modbus/rtu" | "modbus/ascii" | "modbus/rtu-tcp" | "modbus/ascii-tcp" => {
+            ModbusClient::Framed(FramedClient::new(proto, timeout)?)
+        }
+        _ => {
+            unimplemented!("protocol {}", proto.name);
+        }
+    });
+}
+
+// how addresses without the type reference are interpreted: "zero" and
+// "one" set the base of h/i/c/d addresses, with "modicon" plain absolute
+// offsets (=40001) are the conventional references
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Addressing {
+    Zero,
+    One,
+    Modicon,
+}
+
+fn parse_addressing(addressing: &str) -> Addressing {
+    return match addressing.to_lowercase().as_str() {
+        "zero" | "0-based" => Addressing::Zero,
+        "one" | "1-based" => Addressing::One,
+        "modicon" | "conventional" => Addressing::Modicon,
+        _ => panic!("invalid addressing: {}", addressing),
+    };
+}
+
+// 1-based conventional references: 0 - coils, 1 - discretes, 3 - inputs,
+// 4 - holdings
+fn parse_reference(tp: char, number: &str, reg: &str) -> Result<(char, u32), String> {
+    let tp = match tp {
+        '0' => 'c',
+        '1' => 'd',
+        '3' => 'i',
+        '4' => 'h',
+        _ => return Err(format!("invalid register reference: {}", reg)),
+    };
+    return match number.parse::<u32>() {
+        Ok(v) if v > 0 && v <= 0x10000 => Ok((tp, v - 1)),
+        _ => Err(format!("invalid register reference: {}", reg)),
+    };
+}
+
+// register type and 0-based address: h100, h0x1F4, 40001, 400001, 4x0001
+pub(crate) fn parse_register(reg: &str, addressing: Addressing) -> Result<(char, u32), String> {
+    let r = reg.trim();
+    let mut chars = r.chars();
+    let tp = match chars.next() {
+        Some(v) => v,
+        None => return Err("register not specified".to_owned()),
+    };
+    if tp.is_ascii_digit() {
+        return match chars.next() {
+            Some('x') | Some('X') => parse_reference(tp, &r[2..], reg),
+            // 5-digit (40001) and 6-digit (400001) references
+            _ if r.len() == 5 || r.len() == 6 => parse_reference(tp, &r[1..], reg),
+            _ => Err(format!("invalid register: {}", reg)),
+        };
+    }
+    let a = &r[tp.len_utf8()..];
+    let addr = match a.strip_prefix("0x").or_else(|| a.strip_prefix("0X")) {
+        Some(v) => u32::from_str_radix(v, 16).ok(),
+        None => a
+            .split('+')
+            .map(|v| v.parse::<u32>().ok())
+            .sum::<Option<u32>>(),
+    };
+    return match (addr, addressing) {
+        (Some(0), Addressing::One) | (None, _) => Err(format!("invalid register: {}", reg)),
+        (Some(v), Addressing::One) => Ok((tp, v - 1)),
+        (Some(v), _) => Ok((tp, v)),
+    };
+}
+
+fn get_register(reg: &str, addressing: Addressing) -> (char, u32) {
+    return parse_register(reg, addressing).unwrap_or_else(|e| panic!("{}", e));
+}
+
+// the address in h/i/c/d notation, reverse to parse_register
+fn format_register(tp: char, addr: u32, addressing: Addressing) -> String {
+    return match addressing {
+        Addressing::One => format!("{}{}", tp, addr + 1),
+        _ => format!("{}{}", tp, addr),
+    };
+}
+
+// splits the offset into the address and the suffix (register half and bits)
+fn split_offset(offset: &str) -> (&str, &str) {
+    let mut end = offset.find('/').unwrap_or(offset.len());
+    if end > 1 && matches!(&offset[end - 1..end], "h" | "H" | "l" | "L") {
+        end -= 1;
+    }
+    return offset.split_at(end);
+}
+
+// absolute offsets (=X) are converted to 0-based addresses, the register
+// type is checked if specified
+fn normalize_offset(offset: &str, tp: char, addressing: Addressing) -> String {
+    let o = match offset.strip_prefix('=') {
+        Some(v) => v,
+        None => return offset.to_owned(),
+    };
+    let (a, suffix) = split_offset(o);
+    let typed = a.starts_with(|c: char| c.is_ascii_alphabetic())
+        || a.get(1..2).is_some_and(|v| v.eq_ignore_ascii_case("x"));
+    // plain numbers are references only with modicon addressing
+    let (reg_tp, addr) = match (typed, addressing) {
+        (false, Addressing::Zero) => return offset.to_owned(),
+        (false, Addressing::One) => (tp, get_register(&format!("{}{}", tp, a), addressing).1),
+        _ => get_register(a, addressing),
+    };
+    if reg_tp != tp {
+        panic!("offset {} does not match the register type {}", offset, tp);
+    }
+    return format!("={}{}", addr, suffix);
+}
+
+// register type and address range, e.g. h3010-h3019 or h3010-3019
+fn parse_hole(hole: &str, addressing: Addressing) -> (char, u32, u32) {
+    let mut i = hole.splitn(2, '-');
+    let from_reg = i.next().unwrap();
+    let (tp, from) = get_register(from_reg, addressing);
+    let to = match i.next() {
+        // the type can be omitted for the range end
+        Some(v)
+            if v.starts_with(|c: char| c.is_ascii_digit())
+                && !from_reg.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            get_register(&format!("{}{}", tp, v), addressing).1
+        }
+        Some(v) => match get_register(v, addressing) {
+            (t, addr) if t == tp => addr,
+            _ => panic!("invalid hole: {}", hole),
+        },
+        None => from,
+    };
+    if to < from {
+        panic!("invalid hole: {}", hole);
+    }
+    return (tp, from, to);
+}
+
+// max registers (coils) per request, allowed by the protocol
+fn get_pull_limit(tp: char) -> u32 {
+    return match tp {
+        'h' | 'i' => 125,
+        'd' | 'c' => 2000,
+        _ => panic!("unknown register type: {}", tp),
+    };
+}
+
+enum WriteData {
+    Coils(Vec<bool>),
+    Registers(Vec<u16>),
+}
+
+// set-id or address hX / cX (with optional @unit), comma-separated values can
+// be written to the address
+fn prepare_write(
+    target: &str,
+    value: &str,
+    targets: &HashMap<String, ModbusWriteTarget>,
+    default_unit: u8,
+    addressing: Addressing,
+) -> Result<(ModbusRegisterType, u16, u8, WriteData), String> {
+    if value.is_empty() {
+        return Err("value not specified".to_owned());
+    }
+    if let Some(t) = targets.get(target) {
+        let data = match t.tp {
+            ModbusRegisterType::Coil => WriteData::Coils(vec![parse_coil_value(value)?]),
+            ModbusRegisterType::Holding => {
+                WriteData::Registers(encode_value(value, t.data_type, &t.order)?)
+            }
+            _ => return Err(format!("{} is read-only", target)),
+        };
+        return Ok((t.tp, t.addr, t.unit, data));
+    }
+    let (address, unit) = match target.find('@') {
+        Some(pos) => (
+            &target[..pos],
+            target[pos + 1..]
+                .parse::<u8>()
+                .map_err(|_| format!("invalid unit: {}", target))?,
+        ),
+        None => (target, default_unit),
+    };
+    if unit == 0 {
+        return Err("Modbus unit not specified".to_owned());
+    }
+    let (tp, addr) = match parse_register(address, addressing) {
+        Ok(('h', addr)) if addr <= u16::MAX as u32 => (ModbusRegisterType::Holding, addr as u16),
+        Ok(('c', addr)) if addr <= u16::MAX as u32 => (ModbusRegisterType::Coil, addr as u16),
+        Ok(('i', _)) | Ok(('d', _)) => return Err(format!("{} is read-only", target)),
+        _ => return Err(format!("unknown target: {}", target)),
+    };
+    let values = value.split(',');
+    let data = match tp {
+        ModbusRegisterType::Coil => {
+            WriteData::Coils(values.map(parse_coil_value).collect::<Result<_, _>>()?)
+        }
+        _ => WriteData::Registers(values.map(parse_register_value).collect::<Result<_, _>>()?),
+    };
+    return Ok((tp, addr, unit, data));
+}
+
+// TCP stream is out of sync after errors, reconnect on the next loop
+fn drop_stream_client(client: &mut Option<ModbusClient>) {
+    match client {
+        Some(ModbusClient::Tcp(_))
+        | Some(ModbusClient::Framed(FramedClient {
+            link: FrameLink::Tcp(_),
+            ..
+        })) => *client = None,
+        _ => {}
+    }
+}
+
+// returns the command target and the result
+fn process_write_command(
+    client: &mut Option<ModbusClient>,
+    line: &str,
+    targets: &HashMap<String, ModbusWriteTarget>,
+    default_unit: u8,
+    addressing: Addressing,
+) -> (String, Result<(), String>) {
+    let line = line.trim();
+    let target = line.split_whitespace().next().unwrap().to_owned();
+    let value = line[target.len()..].trim();
+    let (tp, addr, unit, data) =
+        match prepare_write(&target, value, targets, default_unit, addressing) {
+            Ok(v) => v,
+            Err(e) => return (target, Err(e)),
+        };
+    let result = match client {
+        Some(ref mut c) => match data {
+            WriteData::Coils(ref v) => write_coils(c, unit, addr, v),
+            WriteData::Registers(ref v) => write_holdings(c, unit, addr, v),
+        },
+        None => Err(rmodbus::ErrorKind::CommunicationError),
+    };
+    return match result {
+        Ok(_) => (target, Ok(())),
+        Err(err) => {
+            if is_comm_error(&err) {
+                drop_stream_client(client);
+            }
+            (target, Err(format!("{:?} ({:?} {})", err, tp, addr)))
+        }
+    };
+}
+
+// group entries with absolute addresses into pulls per register type and unit
+fn plan_pulls(process: Vec<ModbusProcess>, proto: &ModbusProtocol) -> Vec<ModbusPull> {
+    let addressing = parse_addressing(&proto.addressing);
+    let holes: Vec<(char, u32, u32)> = proto
+        .holes
+        .iter()
+        .map(|h| parse_hole(h, addressing))
+        .collect();
+    let mut groups: BTreeMap<(char, u8), Vec<_>> = BTreeMap::new();
+    for mut prc in process {
+        datatypes::check_array_entry(&prc.set_id, prc.count);
+        let (a, suffix) = split_offset(&prc.offset);
+        let (tp, addr) = get_register(a, addressing);
+        let suffix = suffix.to_owned();
+        let limit = get_pull_limit(tp);
+        let offset = parse_process_offset(
+            &match prc.bits {
+                Some(ref bits) => format!("={}{}/{}", addr, suffix, bits),
+                None => format!("={}{}", addr, suffix),
+            },
+            0,
+        );
+        // the address becomes an absolute offset of the planned pull
+        prc.offset = format!("={}{}", format_register(tp, addr, addressing), suffix);
+        let start = offset.offset as u32;
+        let span = get_process_span(&prc, &offset) as u32;
+        if span > limit {
+            panic!("{} does not fit into a single request", prc.set_id);
+        }
+        if start + span > 0x10000 {
+            panic!("address out of range: {}", prc.set_id);
+        }
+        let unit = match prc.unit {
+            0 => proto.unit,
+            v => v,
+        };
+        prc.unit = 0;
+        groups
+            .entry((tp, unit))
+            .or_default()
+            .push((start, start + span - 1, prc));
+    }
+    let mut pulls = Vec::new();
+    for ((tp, unit), mut entries) in groups {
+        let limit = get_pull_limit(tp);
+        entries.sort_by_key(|e| e.0);
+        let mut blocks: Vec<(u32, u32, Vec<ModbusProcess>)> = Vec::new();
+        for (start, end, prc) in entries {
+            if let Some(b) = blocks.last_mut() {
+                // never read across the holes, some devices reject such requests
+                let crosses_hole =
+                    start > b.1 + 1 && holes.iter().any(|h| h.0 == tp && h.1 < start && h.2 > b.1);
+                if start <= b.1 + 1 + proto.max_gap && end.max(b.1) - b.0 < limit && !crosses_hole {
+                    b.1 = end.max(b.1);
+                    b.2.push(prc);
+                    continue;
+                }
+            }
+            blocks.push((start, end, vec![prc]));
+        }
+        for (start, end, process) in blocks {
+            pulls.push(ModbusPull {
+                reg: format_register(tp, start, addressing),
+                count: (end - start + 1) as u16,
+                process,
+                unit,
+                on_error: None,
+                retries: None,
+                backoff_max: None,
+                fifo: false,
+                write: None,
+            });
+        }
+    }
+    return pulls;
+}
+
+// unit IDs, e.g. 1-10,17
+fn parse_units(units: &str) -> Vec<u8> {
+    let mut result = Vec::new();
+    for u in units.split(',') {
+        let mut i = u.trim().splitn(2, '-');
+        let from: u8 = i.next().unwrap().parse().expect("invalid unit ID");
+        let to: u8 = match i.next() {
+            Some(v) => v.parse().expect("invalid unit ID"),
+            None => from,
+        };
+        if to < from {
+            panic!("invalid unit range: {}", u);
+        }
+        result.extend(from..=to);
     }
+    return result;
 }
-fn read_inputs(
+
+// scanned address range and the result: Ok or the error description
+struct ScanBlock {
+    tp: char,
+    start: u32,
+    end: u32,
+    status: Result<(), String>,
+}
+
+fn format_scan_error(err: &rmodbus::ErrorKind) -> String {
+    return match get_exception_code(err) {
+        Some(code) => format!("exception {}", code),
+        None => format!("{:?}", err),
+    };
+}
+
+fn scan_read(
     client: &mut ModbusClient,
-    unit_id: u8,
-    reg: u16,
+    tp: char,
+    unit: u8,
+    addr: u16,
     count: u16,
 ) -> Result<Vec<u16>, rmodbus::ErrorKind> {
-    prepare_client!(client);
-    let mut mreq = prepare_mreq!(unit_id);
-    let mut request = Vec::new();
-    match mreq.generate_get_inputs(reg, count, &mut request) {
-        Ok(_) => {}
-        Err(e) => return Err(e),
-    }
-    let response = match process_request!(client, &request) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(e);
-        }
+    return match tp {
+        'h' => read_holdings(client, unit, addr, count),
+        'i' => read_inputs(client, unit, addr, count),
+        'c' => read_coils(client, unit, addr, count),
+        'd' => read_discretes(client, unit, addr, count),
+        _ => panic!("unknown register type: {}", tp),
     };
-    let mut result = Vec::new();
-    match mreq.parse_u16(&response, &mut result) {
-        Ok(_) => Ok(result),
-        Err(e) => Err(e),
-    }
 }
-fn read_holdings(
+
+// blocks, rejected with "illegal address" or "illegal value", are split
+// until the readable parts are found
+fn scan_block(
     client: &mut ModbusClient,
-    unit_id: u8,
-    reg: u16,
-    count: u16,
-) -> Result<Vec<u16>, rmodbus::ErrorKind> {
-    prepare_client!(client);
-    let mut mreq = prepare_mreq!(unit_id);
-    let mut request = Vec::new();
-    match mreq.generate_get_holdings(reg, count, &mut request) {
-        Ok(_) => {}
-        Err(e) => return Err(e),
+    tp: char,
+    unit: u8,
+    addr: u32,
+    count: u32,
+    blocks: &mut Vec<ScanBlock>,
+) -> Result<(), rmodbus::ErrorKind> {
+    let status = match scan_read(client, tp, unit, addr as u16, count as u16) {
+        Ok(_) => Ok(()),
+        Err(e) if is_comm_error(&e) => return Err(e),
+        // the unit is not available behind the gateway
+        Err(e) if matches!(get_exception_code(&e), Some(0x0a) | Some(0x0b)) => return Err(e),
+        Err(e) => match get_exception_code(&e) {
+            Some(0x02) | Some(0x03) if count > 1 => {
+                let half = count / 2;
+                scan_block(client, tp, unit, addr, half, blocks)?;
+                return scan_block(client, tp, unit, addr + half, count - half, blocks);
+            }
+            _ => Err(format_scan_error(&e)),
+        },
+    };
+    // adjacent blocks with the same result are merged
+    if let Some(b) = blocks.last_mut() {
+        if b.tp == tp && b.end + 1 == addr && b.status == status {
+            b.end = addr + count - 1;
+            return Ok(());
+        }
     }
-    let response = match process_request!(client, &request) {
-        Ok(v) => v,
-        Err(e) => {
-            return Err(e);
+    blocks.push(ScanBlock {
+        tp,
+        start: addr,
+        end: addr + count - 1,
+        status,
+    });
+    return Ok(());
+}
+
+// skeleton pull config for the readable blocks
+fn format_scan_yaml(unit: u8, blocks: &[ScanBlock]) -> String {
+    let mut result = String::new();
+    for b in blocks.iter().filter(|b| b.status.is_ok()) {
+        let limit = get_pull_limit(b.tp);
+        let mut start = b.start;
+        while start <= b.end {
+            let count = (b.end - start + 1).min(limit);
+            result += &format!(
+                "  - reg: {}{}\n    count: {}\n    unit: {}\n",
+                b.tp, start, count, unit
+            );
+            result += "    process:\n      - offset: 0\n";
+            result += &format!("        count: {}\n        start-index: {}\n", count, start);
+            result += &format!("        set-id: unit{}/{}{{index}}\n", unit, b.tp);
+            start += count;
         }
-    };
-    let mut result = Vec::new();
-    match mreq.parse_u16(&response, &mut result) {
-        Ok(_) => Ok(result),
-        Err(e) => Err(e),
     }
+    return result;
 }
 
-enum ModbusClient {
-    Tcp(TcpClient),
-    Udp(UdpClient),
+// probes unit IDs and register ranges, reports the blocks which answer, with
+// "yaml" the skeleton config is printed to stdout and the report to stderr
+#[allow(clippy::too_many_arguments)]
+pub fn scan(
+    proto: &str,
+    target: &str,
+    units: &str,
+    ranges: &str,
+    block: u16,
+    timeout: Duration,
+    yaml: bool,
+    verbose: bool,
+) {
+    let hp = target.to_owned().parse_host_port(DEFAULT_MODBUS_PORT);
+    let connect_client = || -> Option<ModbusClient> {
+        return match proto {
+            "modbus" | "modbus/tcp" => TcpClient::new(&hp.host, hp.port, timeout)
+                .ok()
+                .map(ModbusClient::Tcp),
+            "modbus/udp" => UdpClient::new(&hp.host, hp.port, timeout)
+                .ok()
+                .map(ModbusClient::Udp),
+            _ => unimplemented!("scan for {}", proto),
+        };
+    };
+    let report = |line: String| match yaml {
+        true => eprint(line),
+        false => oprint(line),
+    };
+    let ranges: Vec<(char, u32, u32)> = ranges
+        .split(',')
+        .map(|r| match parse_hole(r.trim(), Addressing::Zero) {
+            (_, _, to) if to > u16::MAX as u32 => panic!("invalid range: {}", r),
+            v => v,
+        })
+        .collect();
+    let units = parse_units(units);
+    let mut client = Some(connect_client().expect("unable to connect to server"));
+    let mut config = String::new();
+    for unit in units {
+        let mut blocks: Vec<ScanBlock> = Vec::new();
+        let mut unit_error = None;
+        'ranges: for (tp, from, to) in &ranges {
+            let limit = get_pull_limit(*tp).min(block as u32).max(1);
+            let mut addr = *from;
+            while addr <= *to {
+                let count = (to - addr + 1).min(limit);
+                if client.is_none() {
+                    client = connect_client();
+                }
+                if verbose {
+                    report(format!("unit {} {}{} count {}", unit, tp, addr, count));
+                }
+                let result = match client {
+                    Some(ref mut c) => scan_block(c, *tp, unit, addr, count, &mut blocks),
+                    None => Err(rmodbus::ErrorKind::CommunicationError),
+                };
+                if let Err(e) = result {
+                    drop_stream_client(&mut client);
+                    // the unit is skipped if it never answered
+                    if blocks.is_empty() {
+                        unit_error = Some(e);
+                        break 'ranges;
+                    }
+                    // the part before the error may be already scanned
+                    let start = match blocks.last() {
+                        Some(b) if b.tp == *tp && b.end >= addr => b.end + 1,
+                        _ => addr,
+                    };
+                    blocks.push(ScanBlock {
+                        tp: *tp,
+                        start,
+                        end: addr + count - 1,
+                        status: Err(format_scan_error(&e)),
+                    });
+                }
+                addr += count;
+            }
+        }
+        if let Some(e) = unit_error {
+            report(format!(
+                "unit {}: no response ({})",
+                unit,
+                format_scan_error(&e)
+            ));
+            continue;
+        }
+        report(format!("unit {}", unit));
+        for b in &blocks {
+            report(format!(
+                "  {}{}-{}{} {}",
+                b.tp,
+                b.start,
+                b.tp,
+                b.end,
+                match b.status {
+                    Ok(_) => "ok",
+                    Err(ref e) => e,
+                }
+            ));
+        }
+        config += &format_scan_yaml(unit, &blocks);
+    }
+    if yaml {
+        oprint(match config.is_empty() {
+            true => "pull: []".to_owned(),
+            false => format!("pull:\n{}", config.trim_end()),
+        });
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -399,26 +2250,30 @@ pub fn run(
     // process config
     let config: ModbusConfig = serde_yaml::from_str(&cfg).unwrap();
     // connect Modbus client
-    let mut client: ModbusClient = match config.proto.name.as_str() {
-        "modbus/tcp" => ModbusClient::Tcp(
-            TcpClient::new(&config.proto.source.host, config.proto.source.port, timeout)
-                .expect("unable to connect to server"),
-        ),
-        "modbus/udp" => ModbusClient::Udp(
-            UdpClient::new(&config.proto.source.host, config.proto.source.port, timeout).unwrap(),
-        ),
-        _ => {
-            unimplemented!("protocol {}", config.proto.name);
-        }
-    };
+    let mut client: Option<ModbusClient> =
+        Some(connect(&config.proto, timeout).expect("unable to connect to server"));
+    let default_order = DataOrder::default().with(
+        config.proto.byte_order.as_ref(),
+        config.proto.word_order.as_ref(),
+    );
     // create pulls and dp list
     let mut pulls: Vec<ModbusPullData> = Vec::new();
     let mut dp_list: Vec<Vec<ModbusDataProcessInfo>> = Vec::new();
-    for p in config.pull {
-        let mut reg = p.reg.clone();
-        let tp = reg.chars().next().unwrap();
-        reg.remove(0);
-        let addr = reg.safe_parse_u32();
+    let mut write_targets: HashMap<String, ModbusWriteTarget> = HashMap::new();
+    let addressing = parse_addressing(&config.proto.addressing);
+    let planned = plan_pulls(config.process, &config.proto);
+    if verbose {
+        for p in &planned {
+            pl::print_debug(&format!(
+                "planned pull {} count {} unit {}",
+                p.reg, p.count, p.unit
+            ));
+        }
+    }
+    let mut pull_list = config.pull;
+    pull_list.extend(planned);
+    for p in pull_list {
+        let (tp, addr) = get_register(&p.reg, addressing);
         let register_type = match tp {
             'h' => ModbusRegisterType::Holding,
             'i' => ModbusRegisterType::Input,
@@ -426,20 +2281,6 @@ pub fn run(
             'c' => ModbusRegisterType::Coil,
             _ => panic!("unknown register type: {}", tp),
         };
-        let mut process_data_vec: Vec<ModbusDataProcessInfo> = Vec::new();
-        for prc in p.process {
-            let offset = prc.offset.parse_data_offset(addr);
-            let tp = match offset.bit {
-                Some(_) => datatypes::GenDataType::Bit,
-                None => prc.r#type.parse_data_type(),
-            };
-            process_data_vec.push(ModbusDataProcessInfo {
-                offset,
-                set_id: prc.set_id,
-                tp,
-                transform: prc.transform,
-            });
-        }
         let mut unit = p.unit;
         if unit == 0 {
             unit = config.proto.unit;
@@ -447,18 +2288,149 @@ pub fn run(
         if unit == 0 {
             panic_any("Modbus unit not specified, neither in pull config, nor default");
         }
+        let function = match (p.fifo, p.write.as_ref()) {
+            (false, None) => PullFunction::Read,
+            (true, None) => PullFunction::Fifo,
+            (false, Some(w)) => {
+                let (write_tp, write_addr) = get_register(&w.reg, addressing);
+                if write_tp != 'h' {
+                    panic!("invalid write register: {}", w.reg);
+                }
+                if w.values.is_empty() || w.values.len() > 121 {
+                    panic!("invalid number of values to write for {}", p.reg);
+                }
+                PullFunction::ReadWrite(write_addr as u16, w.values.clone())
+            }
+            (true, Some(_)) => panic!("{} can not be both FIFO and read / write", p.reg),
+        };
+        if function != PullFunction::Read && register_type != ModbusRegisterType::Holding {
+            panic!(
+                "{}: FIFO and read / write are supported for holdings only",
+                p.reg
+            );
+        }
+        let count = match function {
+            PullFunction::Fifo => FIFO_PULL_SIZE,
+            _ if p.count == 0 => panic!("count not specified for {}", p.reg),
+            _ => p.count,
+        };
+        let mut process_data_vec: Vec<ModbusDataProcessInfo> = Vec::new();
+        for prc in p.process {
+            datatypes::check_array_entry(&prc.set_id, prc.count);
+            if prc.unit != 0 {
+                panic!("unit can not be set for {} inside a pull", prc.set_id);
+            }
+            let prc_offset = normalize_offset(&prc.offset, tp, addressing);
+            let offset = parse_process_offset(
+                &match prc.bits {
+                    Some(ref bits) => format!("{}/{}", prc_offset, bits),
+                    None => prc_offset,
+                },
+                addr,
+            );
+            let tp = get_process_data_type(&prc, &offset);
+            let order = default_order.with(prc.byte_order.as_ref(), prc.word_order.as_ref());
+            let stride = get_process_stride(&prc, &offset, tp);
+            let checks = datatypes::ValueChecks::new(
+                prc.invalid,
+                prc.on_invalid,
+                prc.min,
+                prc.max,
+                prc.stale_after,
+                prc.stale_repeat,
+            );
+            for i in 0..prc.count {
+                let mut o = offset;
+                match o.bit {
+                    Some(bit) => {
+                        let pos = o.offset * 16 + bit as usize + i * stride;
+                        o.offset = pos / 16;
+                        o.bit = Some((pos % 16) as u8);
+                        if pos % 16 + o.bit_count.unwrap_or(1) as usize > 16 {
+                            panic!("bit field out of register: {}", prc.set_id);
+                        }
+                    }
+                    None => o.offset += i * stride,
+                }
+                let set_id = datatypes::format_set_id(&prc.set_id, prc.start_index + i);
+                write_targets.insert(
+                    set_id.clone(),
+                    ModbusWriteTarget {
+                        tp: register_type,
+                        addr: (addr as usize + o.offset) as u16,
+                        unit,
+                        data_type: tp,
+                        order,
+                    },
+                );
+                process_data_vec.push(ModbusDataProcessInfo {
+                    offset: o,
+                    set_id,
+                    tp,
+                    transform: prc.transform.clone(),
+                    alarms: prc.alarms.clone(),
+                    checks: checks.clone(),
+                    order,
+                });
+            }
+        }
         pulls.push(ModbusPullData {
             label: p.reg,
             tp: register_type,
             addr: addr as u16,
-            count: p.count,
+            count,
             unit,
+            on_error: parse_error_policy(p.on_error.as_ref().unwrap_or(&config.proto.on_error)),
+            retries: p.retries.unwrap_or(config.proto.retries),
+            backoff_max: Duration::from_secs_f64(p.backoff_max.unwrap_or(config.proto.backoff_max)),
+            function,
         });
         dp_list.push(process_data_vec);
     }
+    if config.proto.pipeline > 1 && config.proto.name != "modbus/tcp" {
+        panic!("pipelining is supported for modbus/tcp only");
+    }
+    let commands = config.proto.command.as_ref().map(|c| command::listen(c));
     // prepare & launch processor
+    let retry_delay = Duration::from_secs_f64(config.proto.retry_delay);
     let mut pull_loop = IntervalLoop::new(interval);
     let (tx, rx) = mpsc::channel();
+    let no_transform = datatypes::empty_transform_task();
+    let no_alarms = alarm::empty_alarm_list();
+    let no_checks = datatypes::ValueChecks::new(
+        Vec::new(),
+        datatypes::InvalidAction::Null,
+        None,
+        None,
+        None,
+        None,
+    );
+    // device identification is outputted once, at startup
+    if let Some(ref prefix) = config.proto.identify {
+        if config.proto.unit == 0 {
+            panic_any("Modbus unit not specified for device identification");
+        }
+        if let Some(ref mut c) = client {
+            let t = core.create_event_time();
+            match read_device_id(c, config.proto.unit) {
+                Ok(objects) => {
+                    for (object_id, value) in objects {
+                        let id = format!("{}.{}", prefix, get_device_object_name(object_id));
+                        let event = core.create_event(
+                            &id,
+                            datatypes::Value::Str(value),
+                            &no_transform,
+                            &no_alarms,
+                            &no_checks,
+                            &t,
+                        );
+                        core.output(&event);
+                    }
+                }
+                Err(e) => eprint(format!("device identification error: {:?}", e)),
+            }
+        }
+    }
     // data processor
     let processor = thread::spawn(move || loop {
         let w: TaskResult = rx.recv().unwrap();
@@ -470,6 +2442,39 @@ pub fn run(
                     core.clear_event_cache();
                     continue;
                 }
+                TaskCmd::Fail(quality) => {
+                    for d in dp_list.get(w.work_id.unwrap()).unwrap() {
+                        let event = core.create_event(
+                            &d.set_id,
+                            datatypes::Value::Null,
+                            &d.transform,
+                            &d.alarms,
+                            &d.checks,
+                            &t,
+                        );
+                        core.output_quality(&event, quality);
+                    }
+                    continue;
+                }
+                TaskCmd::Ack(target, result) => {
+                    let id = format!("{}.write", target);
+                    let mut event = core.create_event(
+                        &id,
+                        datatypes::Value::Str(match result {
+                            Ok(_) => "ok".to_owned(),
+                            Err(ref e) => e.clone(),
+                        }),
+                        &no_transform,
+                        &no_alarms,
+                        &no_checks,
+                        &t,
+                    );
+                    if result.is_err() {
+                        event.quality = datatypes::Quality::Bad;
+                    }
+                    core.output_direct(&event);
+                    continue;
+                }
                 TaskCmd::Terminate => break,
                 _ => continue,
             },
@@ -480,112 +2485,282 @@ pub fn run(
                 GenDataType::Bit => {
                     let event = core.create_event(
                         &d.set_id,
-                        v.get(d.offset.offset as usize)
+                        v.get(d.offset.offset)
                             .expect(ERROR_OOB)
                             .get_bit(d.offset.bit.unwrap()),
                         &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::BitField => {
+                    let event = core.create_event(
+                        &d.set_id,
+                        v.get(d.offset.offset)
+                            .expect(ERROR_OOB)
+                            .get_bits(d.offset.bit.unwrap(), d.offset.bit_count.unwrap()),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::Uint8 => {
+                    let event = core.create_event(
+                        &d.set_id,
+                        get_register_half(&v, &d.offset),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::Int8 => {
+                    let event = core.create_event(
+                        &d.set_id,
+                        get_register_half(&v, &d.offset) as i8,
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
                         &t,
                     );
                     core.output(&event);
                 }
                 GenDataType::Uint16 => {
+                    let b = d.order.get_bytes(&v, d.offset.offset, 1, false);
                     let event = core.create_event(
                         &d.set_id,
-                        *v.get(d.offset.offset as usize).expect(ERROR_OOB),
+                        u16::from_be_bytes([b[0], b[1]]),
                         &d.transform,
+                        &d.alarms,
+                        &d.checks,
                         &t,
                     );
                     core.output(&event);
                 }
                 GenDataType::Int16 => {
+                    let b = d.order.get_bytes(&v, d.offset.offset, 1, false);
                     let event = core.create_event(
                         &d.set_id,
-                        *v.get(d.offset.offset as usize).expect(ERROR_OOB) as i16,
+                        i16::from_be_bytes([b[0], b[1]]),
                         &d.transform,
+                        &d.alarms,
+                        &d.checks,
                         &t,
                     );
                     core.output(&event);
                 }
                 GenDataType::Uint32 => {
-                    let v1 = v.get(d.offset.offset as usize).expect(ERROR_OOB);
-                    let v2 = v.get((d.offset.offset + 1) as usize).expect(ERROR_OOB);
+                    let b = d.order.get_bytes(&v, d.offset.offset, 2, false);
                     let event = core.create_event(
                         &d.set_id,
-                        *v1 as u32 * 65536 + *v2 as u32,
+                        u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
                         &d.transform,
+                        &d.alarms,
+                        &d.checks,
                         &t,
                     );
                     core.output(&event);
                 }
                 GenDataType::Int32 => {
-                    let v1 = v
-                        .get(d.offset.offset as usize)
-                        .unwrap_or_else(|| panic_any(ERROR_OOB));
-                    let v2 = v
-                        .get((d.offset.offset + 1) as usize)
-                        .unwrap_or_else(|| panic_any(ERROR_OOB));
+                    let b = d.order.get_bytes(&v, d.offset.offset, 2, false);
                     let event = core.create_event(
                         &d.set_id,
-                        (*v1 as u32 * 65536 + *v2 as u32) as i32,
+                        i32::from_be_bytes([b[0], b[1], b[2], b[3]]),
                         &d.transform,
+                        &d.alarms,
+                        &d.checks,
                         &t,
                     );
                     core.output(&event);
                 }
                 GenDataType::Uint64 => {
-                    let v1 = *v.get(d.offset.offset as usize).expect(ERROR_OOB) as u64;
-                    let v2 = *v.get((d.offset.offset + 1) as usize).expect(ERROR_OOB) as u64;
-                    let v3 = *v.get((d.offset.offset + 2) as usize).expect(ERROR_OOB) as u64;
-                    let v4 = *v.get((d.offset.offset + 3) as usize).expect(ERROR_OOB) as u64;
-                    let value = u64::from_be_bytes([
-                        (v1 >> 8) as u8,
-                        v1 as u8,
-                        (v2 >> 8) as u8,
-                        v2 as u8,
-                        (v3 >> 8) as u8,
-                        v3 as u8,
-                        (v4 >> 8) as u8,
-                        v4 as u8,
-                    ]);
-                    let event = core.create_event(&d.set_id, value, &d.transform, &t);
+                    let b = d.order.get_bytes(&v, d.offset.offset, 4, false);
+                    let value =
+                        u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+                    let event =
+                        core.create_event(&d.set_id, value, &d.transform, &d.alarms, &d.checks, &t);
                     core.output(&event);
                 }
                 GenDataType::Int64 => {
-                    let v1 = *v.get(d.offset.offset as usize).expect(ERROR_OOB) as u64;
-                    let v2 = *v.get((d.offset.offset + 1) as usize).expect(ERROR_OOB) as u64;
-                    let v3 = *v.get((d.offset.offset + 2) as usize).expect(ERROR_OOB) as u64;
-                    let v4 = *v.get((d.offset.offset + 3) as usize).expect(ERROR_OOB) as u64;
-                    let value = u64::from_be_bytes([
-                        (v1 >> 8) as u8,
-                        v1 as u8,
-                        (v2 >> 8) as u8,
-                        v2 as u8,
-                        (v3 >> 8) as u8,
-                        v3 as u8,
-                        (v4 >> 8) as u8,
-                        v4 as u8,
-                    ]);
-                    let event = core.create_event(&d.set_id, value as i64, &d.transform, &t);
+                    let b = d.order.get_bytes(&v, d.offset.offset, 4, false);
+                    let value =
+                        i64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+                    let event =
+                        core.create_event(&d.set_id, value, &d.transform, &d.alarms, &d.checks, &t);
                     core.output(&event);
                 }
                 GenDataType::Real32 => {
-                    let v1 = v
-                        .get(d.offset.offset as usize)
-                        .unwrap_or_else(|| panic_any(ERROR_OOB));
-                    let v2 = v
-                        .get((d.offset.offset + 1) as usize)
-                        .unwrap_or_else(|| panic_any(ERROR_OOB));
-                    let val: f32 = Ieee754::from_bits(*v2 as u32 * 65536 + *v1 as u32);
-                    let event = core.create_event(&d.set_id, val, &d.transform, &t);
+                    let b = d.order.get_bytes(&v, d.offset.offset, 2, true);
+                    let val: f32 = Ieee754::from_bits(u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+                    let event =
+                        core.create_event(&d.set_id, val, &d.transform, &d.alarms, &d.checks, &t);
+                    core.output(&event);
+                }
+                GenDataType::Str(_) | GenDataType::StrBytes(_) => {
+                    let len = match d.tp {
+                        GenDataType::StrBytes(len) => len,
+                        _ => get_register_count(d.tp) * 2,
+                    };
+                    let event = core.create_event(
+                        &d.set_id,
+                        d.order.get_str(&v, d.offset.offset, len),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::Bcd16 | GenDataType::Bcd32 => {
+                    let (count, nibbles) = match d.tp {
+                        GenDataType::Bcd16 => (1, 4),
+                        _ => (2, 8),
+                    };
+                    let b = d.order.get_bytes(&v, d.offset.offset, count, false);
+                    let raw = b.iter().fold(0u64, |a, x| a << 8 | *x as u64);
+                    match decode_bcd(raw, nibbles) {
+                        Some(value) => {
+                            let event = core.create_event(
+                                &d.set_id,
+                                value,
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output(&event);
+                        }
+                        // the value is outputted as bad, not kept
+                        None => {
+                            eprint(format!(
+                                "decode error: {} invalid BCD value 0x{:x}",
+                                d.set_id, raw
+                            ));
+                            let event = core.create_event(
+                                &d.set_id,
+                                datatypes::Value::Null,
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output_quality(&event, datatypes::Quality::Bad);
+                        }
+                    }
+                }
+                GenDataType::Fixed(decimals) => {
+                    let b = d.order.get_bytes(&v, d.offset.offset, 1, false);
+                    let event = core.create_event(
+                        &d.set_id,
+                        decode_fixed(i16::from_be_bytes([b[0], b[1]]) as i64, decimals),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::Fixed32(decimals) => {
+                    let b = d.order.get_bytes(&v, d.offset.offset, 2, false);
+                    let event = core.create_event(
+                        &d.set_id,
+                        decode_fixed(
+                            i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as i64,
+                            decimals,
+                        ),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::UnixTime32 => {
+                    let b = d.order.get_bytes(&v, d.offset.offset, 2, false);
+                    let event = core.create_event(
+                        &d.set_id,
+                        core.timestamp_value(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f64),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::UnixTime64 | GenDataType::UnixTime64Ms => {
+                    let b = d.order.get_bytes(&v, d.offset.offset, 4, false);
+                    let mut secs =
+                        u64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64;
+                    if d.tp == GenDataType::UnixTime64Ms {
+                        secs /= 1_000.0;
+                    }
+                    let event = core.create_event(
+                        &d.set_id,
+                        core.timestamp_value(secs),
+                        &d.transform,
+                        &d.alarms,
+                        &d.checks,
+                        &t,
+                    );
+                    core.output(&event);
+                }
+                GenDataType::DateTime => {
+                    let r = v
+                        .get(d.offset.offset..d.offset.offset + 6)
+                        .expect(ERROR_OOB);
+                    match datatypes::datetime_to_timestamp(
+                        r[0] as i32,
+                        r[1] as u32,
+                        r[2] as u32,
+                        r[3] as u32,
+                        r[4] as u32,
+                        r[5] as u32,
+                        0,
+                    ) {
+                        Some(secs) => {
+                            let event = core.create_event(
+                                &d.set_id,
+                                core.timestamp_value(secs),
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output(&event);
+                        }
+                        None => {
+                            eprint(format!("decode error: {} invalid date {:?}", d.set_id, r));
+                            let event = core.create_event(
+                                &d.set_id,
+                                datatypes::Value::Null,
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output_quality(&event, datatypes::Quality::Bad);
+                        }
+                    }
+                }
+                GenDataType::Real64 => {
+                    let val = d.order.get_real64(&v, d.offset.offset);
+                    let event =
+                        core.create_event(&d.set_id, val, &d.transform, &d.alarms, &d.checks, &t);
                     core.output(&event);
                 }
-                _ => unimplemented!("data type unimplemented: {}", d.tp),
             };
         }
     });
     // pulling loop
     let mut resend_time = resend_interval.map(|v| Instant::now() + v);
     let mut pull_log: datatypes::PullLog = datatypes::PullLog::new();
+    // next attempt time and the current delay of the failed pulls
+    let mut backoff: Vec<Option<(Instant, Duration)>> = vec![None; pulls.len()];
     loop {
         if verbose_warnings {
             pull_log.clear();
@@ -599,9 +2774,41 @@ pub fn run(
                 clear_processor_cache!(processor, tx);
             }
         }
-        for i in 0..pulls.len() {
+        // reconnect after communication errors
+        if client.is_none() {
+            if verbose {
+                pl::print_debug(&"reconnecting".to_owned());
+            }
+            client = connect(&config.proto, timeout).ok();
+        }
+        // pipelined pulls are sent at once, the results are processed below
+        let mut prefetched: Vec<Option<Result<Vec<u16>, rmodbus::ErrorKind>>> =
+            pulls.iter().map(|_| None).collect();
+        if config.proto.pipeline > 1 {
+            if let Some(ModbusClient::Tcp(ref mut c)) = client {
+                let now = Instant::now();
+                // extended function pulls are not pipelined
+                let active: Vec<usize> = (0..pulls.len())
+                    .filter(|i| pulls[*i].function == PullFunction::Read)
+                    .filter(|i| !matches!(backoff[*i], Some((next, _)) if now < next))
+                    .collect();
+                for chunk in active.chunks(config.proto.pipeline) {
+                    let chunk_pulls: Vec<&ModbusPullData> =
+                        chunk.iter().map(|i| &pulls[*i]).collect();
+                    for (i, result) in chunk.iter().zip(read_pipelined(c, &chunk_pulls)) {
+                        prefetched[*i] = Some(result);
+                    }
+                }
+            }
+        }
+        for (i, p) in pulls.iter().enumerate() {
+            // the pull is delayed after errors
+            if let Some((next, _)) = backoff[i] {
+                if Instant::now() < next {
+                    continue;
+                }
+            }
             let call_time = core.create_event_time();
-            let p = pulls.get(i).unwrap();
             let mut pull_log_entry = match verbose_warnings {
                 true => Some(datatypes::PullLogEntry::new(&p.label)),
                 false => None,
@@ -609,17 +2816,41 @@ pub fn run(
             if verbose {
                 pl::print_debug(&format!("reading registers {:?}", p));
             }
-            let data = match p.tp {
-                ModbusRegisterType::Holding => read_holdings(&mut client, p.unit, p.addr, p.count),
-                ModbusRegisterType::Input => read_inputs(&mut client, p.unit, p.addr, p.count),
-                ModbusRegisterType::Coil => read_coils(&mut client, p.unit, p.addr, p.count),
-                ModbusRegisterType::Discrete => {
-                    read_discretes(&mut client, p.unit, p.addr, p.count)
+            let mut attempt = 0;
+            let mut prefetched_data = prefetched[i].take();
+            let data = loop {
+                let data = match prefetched_data.take() {
+                    Some(v) => v,
+                    None => match client {
+                        Some(ref mut c) => read_pull(c, p),
+                        None => Err(rmodbus::ErrorKind::CommunicationError),
+                    },
+                };
+                match data {
+                    Err(err) if attempt < p.retries => {
+                        attempt += 1;
+                        if verbose {
+                            pl::print_debug(&format!(
+                                "{} error {:?}, retry {}",
+                                p.label, err, attempt
+                            ));
+                        }
+                        // give the device (or the link) time to recover
+                        thread::sleep(retry_delay);
+                        if is_comm_error(&err) {
+                            drop_stream_client(&mut client);
+                            if client.is_none() {
+                                client = connect(&config.proto, timeout).ok();
+                            }
+                        }
+                    }
+                    _ => break data,
                 }
             };
             log_pulled!(pull_log_entry);
             tx.send(match data {
                 Ok(v) => {
+                    backoff[i] = None;
                     if verbose {
                         pl::print_debug(&format!("{:?}", v));
                     }
@@ -630,13 +2861,78 @@ pub fn run(
                         cmd: TaskCmd::Process,
                     }
                 }
-                Err(err) => panic!("{:?}", err),
+                Err(err) if is_comm_error(&err) => {
+                    if verbose {
+                        pl::print_debug(&format!("{} error {:?}", p.label, err));
+                    }
+                    drop_stream_client(&mut client);
+                    if p.on_error == ErrorPolicy::Backoff {
+                        backoff[i] = Some(get_backoff(backoff[i], interval, p, verbose));
+                    }
+                    TaskResult {
+                        data: None,
+                        work_id: Some(i),
+                        t: call_time,
+                        cmd: TaskCmd::Fail(datatypes::Quality::CommFailure),
+                    }
+                }
+                Err(err) => {
+                    let code = get_exception_code(&err);
+                    if verbose {
+                        pl::print_debug(&match code {
+                            Some(c) => format!("{} error {:?}, exception code {}", p.label, err, c),
+                            None => format!("{} error {:?}", p.label, err),
+                        });
+                    }
+                    match p.on_error {
+                        ErrorPolicy::Fail => panic!("{} error {:?}", p.label, err),
+                        ErrorPolicy::Skip => {}
+                        ErrorPolicy::Backoff => {
+                            backoff[i] = Some(get_backoff(backoff[i], interval, p, verbose));
+                        }
+                    }
+                    TaskResult {
+                        data: None,
+                        work_id: Some(i),
+                        t: call_time,
+                        cmd: TaskCmd::Fail(match code {
+                            Some(c) => datatypes::Quality::Exception(c),
+                            None => datatypes::Quality::Bad,
+                        }),
+                    }
+                }
             })
             .unwrap();
             if verbose_warnings {
                 pull_log.push_entry(pull_log_entry.unwrap());
             }
         }
+        // write commands are processed between the pull cycles
+        if let Some(ref commands) = commands {
+            while let Ok(cmd) = commands.try_recv() {
+                if verbose {
+                    pl::print_debug(&format!("command {}", cmd.line));
+                }
+                let (target, result) = process_write_command(
+                    &mut client,
+                    &cmd.line,
+                    &write_targets,
+                    config.proto.unit,
+                    addressing,
+                );
+                if verbose {
+                    pl::print_debug(&format!("{} write {:?}", target, result));
+                }
+                cmd.reply(result.clone());
+                tx.send(TaskResult {
+                    data: None,
+                    work_id: None,
+                    t: core.create_event_time(),
+                    cmd: TaskCmd::Ack(target, result),
+                })
+                .unwrap();
+            }
+        }
         if !inloop || core.is_event_timeout() {
             break;
         }
@@ -645,3 +2941,236 @@ pub fn run(
     }
     terminate_processor!(processor, tx);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_process(yaml: &str, addr: u32) -> (datatypes::DataOffset, GenDataType) {
+        let prc: ModbusProcess = serde_yaml::from_str(yaml).unwrap();
+        let offset = parse_process_offset(&prc.offset, addr);
+        let tp = get_process_data_type(&prc, &offset);
+        return (offset, tp);
+    }
+
+    fn order(byte_order: &str) -> DataOrder {
+        return DataOrder::default().with(Some(&byte_order.to_owned()), None);
+    }
+
+    // 1234.5678 = 0x40934a456d5cfaad, the first register is padding
+    const REAL64_ABCD: [u16; 5] = [0xffff, 0x4093, 0x4a45, 0x6d5c, 0xfaad];
+    const REAL64_CDAB: [u16; 5] = [0xffff, 0xfaad, 0x6d5c, 0x4a45, 0x4093];
+    const REAL64_BADC: [u16; 5] = [0xffff, 0x9340, 0x454a, 0x5c6d, 0xadfa];
+    const REAL64_DCBA: [u16; 5] = [0xffff, 0xadfa, 0x5c6d, 0x454a, 0x9340];
+
+    // register 5 is 0x80fe
+    const HALVES: [u16; 6] = [0, 0, 0, 0, 0, 0x80fe];
+
+    #[test]
+    fn test_real64_word_order() {
+        let (offset, tp) = decode_process("{offset: 1, type: real64, set-id: test}", 0);
+        assert_eq!(tp, GenDataType::Real64);
+        assert_eq!(get_register_count(tp), 4);
+        for (regs, byte_order) in &[
+            (REAL64_ABCD, "abcd"),
+            (REAL64_CDAB, "cdab"),
+            (REAL64_BADC, "badc"),
+            (REAL64_DCBA, "dcba"),
+        ] {
+            assert_eq!(
+                order(byte_order).get_real64(regs, offset.offset),
+                1234.5678,
+                "{}",
+                byte_order
+            );
+        }
+        // reals are decoded low-word-first by default
+        assert_eq!(
+            DataOrder::default().get_real64(&REAL64_CDAB, offset.offset),
+            1234.5678
+        );
+        assert_eq!(
+            order("big").get_real64(&REAL64_CDAB, offset.offset),
+            1234.5678
+        );
+    }
+
+    #[test]
+    fn test_int8_halves() {
+        let (high, tp) = decode_process("{offset: 5h, type: int8, set-id: test}", 0);
+        assert_eq!(tp, GenDataType::Int8);
+        assert_eq!(high.half, Some(datatypes::RegisterHalf::High));
+        assert_eq!(get_register_half(&HALVES, &high) as i8, -128);
+        let (low, tp) = decode_process("{offset: 5l, type: int8, set-id: test}", 0);
+        assert_eq!(tp, GenDataType::Int8);
+        assert_eq!(low.half, Some(datatypes::RegisterHalf::Low));
+        assert_eq!(get_register_half(&HALVES, &low) as i8, -2);
+    }
+
+    #[test]
+    fn test_uint8_halves() {
+        let (high, tp) = decode_process("{offset: 5H, type: uint8, set-id: test}", 0);
+        assert_eq!(tp, GenDataType::Uint8);
+        assert_eq!(get_register_half(&HALVES, &high), 0x80);
+        let (low, tp) = decode_process("{offset: 5L, type: uint8, set-id: test}", 0);
+        assert_eq!(tp, GenDataType::Uint8);
+        assert_eq!(get_register_half(&HALVES, &low), 0xfe);
+        // low byte without the half specified
+        let (offset, _) = decode_process("{offset: 5, type: uint8, set-id: test}", 0);
+        assert_eq!(offset.half, None);
+        assert_eq!(get_register_half(&HALVES, &offset), 0xfe);
+        // word halves are read as uint8
+        let (high, tp) = decode_process("{offset: 5h, set-id: test}", 0);
+        assert_eq!(tp, GenDataType::Uint8);
+        assert_eq!(get_register_half(&HALVES, &high), 0x80);
+    }
+
+    #[test]
+    fn test_halves_absolute_and_bits() {
+        let (offset, tp) = decode_process("{offset: =3005h, type: int8, set-id: test}", 3000);
+        assert_eq!(offset.offset, 5);
+        assert_eq!(tp, GenDataType::Int8);
+        assert_eq!(get_register_half(&HALVES, &offset) as i8, -128);
+        let (offset, tp) = decode_process("{offset: 5/15, set-id: test}", 0);
+        assert_eq!(offset.half, None);
+        assert_eq!(tp, GenDataType::Bit);
+        assert_eq!(HALVES[offset.offset].get_bit(offset.bit.unwrap()), 1);
+    }
+
+    #[test]
+    fn test_string_bytes() {
+        let tp = "string(7b)".to_owned().parse_data_type();
+        assert_eq!(tp, GenDataType::StrBytes(7));
+        assert_eq!(tp.to_string(), "string(7b)");
+        assert_eq!(get_register_count(tp), 4);
+        let regs = encode_value("serial1", tp, &DataOrder::default()).unwrap();
+        assert_eq!(regs, vec![0x7365, 0x7269, 0x616c, 0x3100]);
+        assert_eq!(DataOrder::default().get_str(&regs, 0, 7), "serial1");
+        assert!(encode_value("serial12", tp, &DataOrder::default()).is_err());
+        // the second byte of the last register is ignored
+        let regs = [0x4142, 0x4344];
+        assert_eq!(DataOrder::default().get_str(&regs, 0, 3), "ABC");
+        assert_eq!(order("little").get_str(&[0x4241, 0x4443], 0, 3), "ABC");
+        assert_eq!(
+            "string(2)".to_owned().parse_data_type(),
+            GenDataType::Str(Some(2))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_half_of_int16() {
+        decode_process("{offset: 5h, type: int16, set-id: test}", 0);
+    }
+
+    #[test]
+    fn test_normalize_offset() {
+        assert_eq!(normalize_offset("=3005", 'h', Addressing::Zero), "=3005");
+        assert_eq!(normalize_offset("=3005", 'h', Addressing::One), "=3004");
+        assert_eq!(normalize_offset("=43006", 'h', Addressing::One), "=43005");
+        assert_eq!(normalize_offset("=4x3006", 'h', Addressing::One), "=3005");
+        assert_eq!(
+            normalize_offset("=43006", 'h', Addressing::Modicon),
+            "=3005"
+        );
+        assert_eq!(
+            normalize_offset("=h3005/2", 'h', Addressing::Zero),
+            "=3005/2"
+        );
+    }
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_units("1, 3-5,7-7"), vec![1, 3, 4, 5, 7]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_units_reversed() {
+        parse_units("1-0");
+    }
+
+    #[test]
+    fn test_response_frame_len() {
+        let len = get_response_frame_len;
+        assert_eq!(len(&[1, 3, 250], ModbusProto::Rtu).unwrap(), 255);
+        assert_eq!(len(&[1, 0x83, 2], ModbusProto::Rtu).unwrap(), 5);
+        assert_eq!(len(b":0103FA", ModbusProto::Ascii).unwrap(), 511);
+        assert_eq!(len(b":01037C", ModbusProto::Ascii).unwrap(), 259);
+        assert_eq!(len(b":010602", ModbusProto::Ascii).unwrap(), 17);
+        assert!(len(b":01ZZ02", ModbusProto::Ascii).is_err());
+    }
+
+    #[test]
+    fn test_ascii_long_response() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // read 125 holdings, 250 data bytes
+        let mut data = vec![1, 3, 250];
+        data.extend((0..250).map(|v| v as u8));
+        data.push(calc_lrc(&data));
+        let slave_data = data.clone();
+        let slave = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            while request.last() != Some(&b'\n') {
+                let mut b = [0u8; 1];
+                stream.read_exact(&mut b).unwrap();
+                request.push(b[0]);
+            }
+            let mut frame = Vec::new();
+            generate_ascii_frame(&slave_data, &mut frame).unwrap();
+            stream.write_all(&frame).unwrap();
+        });
+        let timeout = Duration::from_secs(2);
+        let mut client = FramedClient {
+            link: FrameLink::Tcp(TcpStream::connect_timeout(&addr, timeout).unwrap()),
+            proto: ModbusProto::Ascii,
+            timeout,
+            frame_delay: Duration::from_secs(0),
+            last_frame: Instant::now(),
+        };
+        let response = client.read_response(&[1, 3, 0, 0, 0, 125]).unwrap();
+        slave.join().unwrap();
+        assert_eq!(response, data);
+    }
+
+    // valid and corrupted RTU responses, then the slave is silent
+    #[cfg(unix)]
+    #[test]
+    fn test_rtu_crc_and_timeout() {
+        let (mut master, path) = crate::serial::open_pty();
+        let slave = thread::spawn(move || {
+            let mut response = vec![0x01, 0x03, 0x02, 0x00, 0x2a, 0x39, 0x9b];
+            let mut request = [0u8; 8];
+            for _ in 0..2 {
+                master.read_exact(&mut request).unwrap();
+                assert_eq!(request, [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0a]);
+                master.write_all(&response).unwrap();
+                response[6] ^= 0xff;
+            }
+            master.read_exact(&mut request).unwrap();
+            master
+        });
+        let timeout = Duration::from_millis(200);
+        let mut client = ModbusClient::Framed(FramedClient {
+            link: FrameLink::Serial(SerialPort::open(&path, 9600, 8, "N", 1).unwrap()),
+            proto: ModbusProto::Rtu,
+            timeout,
+            frame_delay: Duration::from_secs(0),
+            last_frame: Instant::now(),
+        });
+        assert_eq!(read_holdings(&mut client, 1, 0, 1).unwrap(), vec![42]);
+        assert_eq!(
+            read_holdings(&mut client, 1, 0, 1).unwrap_err(),
+            rmodbus::ErrorKind::FrameCRCError
+        );
+        let started = Instant::now();
+        assert_eq!(
+            read_holdings(&mut client, 1, 0, 1).unwrap_err(),
+            rmodbus::ErrorKind::CommunicationError
+        );
+        assert!(started.elapsed() >= timeout);
+        let _master = slave.join().unwrap();
+    }
+}
@@ -5,6 +5,7 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
 
+use pl::alarm;
 use pl::datatypes;
 
 const DEFAULT_SNMP_PORT: u16 = 161;
@@ -66,6 +67,32 @@ struct SNMPProcess {
     set_id: Option<String>,
     #[serde(default = "datatypes::empty_transform_task")]
     transform: datatypes::EventTransformList,
+    #[serde(default = "alarm::empty_alarm_list")]
+    alarms: alarm::AlarmList,
+    // sentinel values, reported as null
+    #[serde(default = "datatypes::get_default_invalid_values")]
+    invalid: Vec<f64>,
+    #[serde(
+        alias = "on-invalid",
+        default = "datatypes::get_default_invalid_action",
+        deserialize_with = "datatypes::de_invalid_action"
+    )]
+    on_invalid: datatypes::InvalidAction,
+    // output "out-of-range" quality if the value (after transformations) is
+    // out of range
+    #[serde(default = "datatypes::get_default_range_min")]
+    min: Option<f64>,
+    #[serde(default = "datatypes::get_default_range_max")]
+    max: Option<f64>,
+    // seconds, output "stale" quality event if the value isn't changed
+    #[serde(alias = "stale-after", default = "datatypes::get_default_stale_after")]
+    stale_after: Option<f64>,
+    // seconds, repeat the stale event
+    #[serde(
+        alias = "stale-repeat",
+        default = "datatypes::get_default_stale_repeat"
+    )]
+    stale_repeat: Option<f64>,
 }
 
 // TODO: move some fields to de_
@@ -82,6 +109,8 @@ struct SNMPDataProcessInfo {
     oid: String,
     set_id: Option<String>,
     transform: datatypes::EventTransformList,
+    alarms: alarm::AlarmList,
+    checks: datatypes::ValueChecks,
 }
 
 #[derive(Debug)]
@@ -91,6 +120,7 @@ enum SNMPValue {
     SInt64(i64),
     SUint64(u64),
     SStr(String),
+    SOctets(Vec<u8>),
     SNull,
 }
 
@@ -106,7 +136,8 @@ fn parse_snmp_val(value: snmp::Value) -> SNMPValue {
             }
         }
         Integer(v) => SInt64(v),
-        OctetString(v) => SStr(String::from_utf8_lossy(v).to_string()),
+        // raw octets are kept for hex
+        OctetString(v) => SOctets(v.to_vec()),
         ObjectIdentifier(ref v) => SStr(v.to_string()),
         IpAddress(v) => SStr(format!("{}.{}.{}.{}", v[0], v[1], v[2], v[3])),
         Counter32(v) => SUint32(v),
@@ -156,6 +187,15 @@ pub fn run(
                 oid: prepare_oid(&prc.oid),
                 set_id: prc.set_id,
                 transform: prc.transform,
+                alarms: prc.alarms,
+                checks: datatypes::ValueChecks::new(
+                    prc.invalid,
+                    prc.on_invalid,
+                    prc.min,
+                    prc.max,
+                    prc.stale_after,
+                    prc.stale_repeat,
+                ),
             });
         }
         let mut oids: Vec<Vec<u32>> = Vec::new();
@@ -209,6 +249,24 @@ pub fn run(
                     core.clear_event_cache();
                     continue;
                 }
+                TaskCmd::Fail(quality) => {
+                    for d in dp_list.get(w.work_id.unwrap()).unwrap() {
+                        let id = match &d.set_id {
+                            Some(v) => v,
+                            None => &d.oid,
+                        };
+                        let event = core.create_event(
+                            id,
+                            datatypes::Value::Null,
+                            &d.transform,
+                            &d.alarms,
+                            &d.checks,
+                            &t,
+                        );
+                        core.output_quality(&event, quality);
+                    }
+                    continue;
+                }
                 TaskCmd::Terminate => break,
                 _ => continue,
             },
@@ -217,7 +275,7 @@ pub fn run(
         for d in dp_list.get(i).unwrap() {
             macro_rules! process_snmp_result {
                 ($i:path, $v:path) => {
-                    let event = core.create_event(&$i, *$v, &d.transform, &t);
+                    let event = core.create_event(&$i, *$v, &d.transform, &d.alarms, &d.checks, &t);
                     core.output(&event);
                 };
             }
@@ -242,7 +300,25 @@ pub fn run(
                             process_snmp_result!(id, v);
                         }
                         SStr(v) => {
-                            let event = core.create_event(id, v.to_owned(), &d.transform, &t);
+                            let event = core.create_event(
+                                id,
+                                v.to_owned(),
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
+                            core.output(&event);
+                        }
+                        SOctets(v) => {
+                            let event = core.create_event(
+                                id,
+                                datatypes::OctetString(v.to_owned()),
+                                &d.transform,
+                                &d.alarms,
+                                &d.checks,
+                                &t,
+                            );
                             core.output(&event);
                         }
                         SNull => {
@@ -282,7 +358,7 @@ pub fn run(
                 false => None,
             };
             // TODO: move slices to prepare stage
-            if p.bulk {
+            let result = if p.bulk {
                 if verbose {
                     pl::print_debug(&format!("SNMP GETBULK {:?}", p.oids));
                 }
@@ -290,46 +366,57 @@ pub fn run(
                 for o in &p.oids {
                     z.push(o.as_slice());
                 }
-                let response = sess
-                    .getbulk(z.as_slice(), p.non_repeat, p.max_repeat)
-                    .expect("SNMP GETBULK error");
-                let mut result: HashMap<String, SNMPValue> = HashMap::new();
-                for (name, val) in response.varbinds {
-                    result.insert(name.to_string(), parse_snmp_val(val));
+                match sess.getbulk(z.as_slice(), p.non_repeat, p.max_repeat) {
+                    Ok(response) => {
+                        let mut result: HashMap<String, SNMPValue> = HashMap::new();
+                        for (name, val) in response.varbinds {
+                            result.insert(name.to_string(), parse_snmp_val(val));
+                        }
+                        Ok(result)
+                    }
+                    Err(e) => Err(format!("SNMP GETBULK error {:?}", e)),
                 }
-                debug_snmp_result!(result);
-                log_pulled!(pull_log_entry);
-                tx.send(TaskResult {
-                    data: Some(result),
-                    work_id: Some(work_id),
-                    t: call_time,
-                    cmd: TaskCmd::Process,
-                })
-                .unwrap();
             } else {
                 let o = p.oids.get(0).unwrap();
                 if verbose {
                     pl::print_debug(&format!("SNMP GET {:?}", o));
                 }
-                let mut response = sess
-                    .getnext(o.as_slice())
-                    .unwrap_or_else(|_| panic!("SNMP GET error {:?}", o));
-                let (name, val) = response
-                    .varbinds
-                    .next()
-                    .unwrap_or_else(|| panic!("SNMP GET parse error {:?}", o));
-                let mut result: HashMap<String, SNMPValue> = HashMap::new();
-                result.insert(name.to_string(), parse_snmp_val(val));
-                debug_snmp_result!(result);
-                log_pulled!(pull_log_entry);
-                tx.send(TaskResult {
-                    data: Some(result),
-                    work_id: Some(work_id),
-                    t: call_time,
-                    cmd: TaskCmd::Process,
-                })
-                .unwrap();
-            }
+                match sess.getnext(o.as_slice()) {
+                    Ok(mut response) => match response.varbinds.next() {
+                        Some((name, val)) => {
+                            let mut result: HashMap<String, SNMPValue> = HashMap::new();
+                            result.insert(name.to_string(), parse_snmp_val(val));
+                            Ok(result)
+                        }
+                        None => Err(format!("SNMP GET parse error {:?}", o)),
+                    },
+                    Err(e) => Err(format!("SNMP GET error {:?} {:?}", o, e)),
+                }
+            };
+            log_pulled!(pull_log_entry);
+            tx.send(match result {
+                Ok(result) => {
+                    debug_snmp_result!(result);
+                    TaskResult {
+                        data: Some(result),
+                        work_id: Some(work_id),
+                        t: call_time,
+                        cmd: TaskCmd::Process,
+                    }
+                }
+                Err(e) => {
+                    if verbose {
+                        pl::print_debug(&e);
+                    }
+                    TaskResult {
+                        data: None,
+                        work_id: Some(work_id),
+                        t: call_time,
+                        cmd: TaskCmd::Fail(datatypes::Quality::CommFailure),
+                    }
+                }
+            })
+            .unwrap();
             if verbose_warnings {
                 pull_log.push_entry(pull_log_entry.unwrap())
             };
@@ -0,0 +1,251 @@
+// serial port (RS-232 / RS-485) access via termios
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+fn parse_parity(parity: &str) -> Parity {
+    return match parity.to_lowercase().as_str() {
+        "none" | "n" => Parity::None,
+        "even" | "e" => Parity::Even,
+        "odd" | "o" => Parity::Odd,
+        _ => panic!("invalid parity: {}", parity),
+    };
+}
+
+fn get_speed(baud_rate: u32) -> libc::speed_t {
+    return match baud_rate {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _ => panic!("unsupported baud rate: {}", baud_rate),
+    };
+}
+
+fn get_char_size(data_bits: u8) -> libc::tcflag_t {
+    return match data_bits {
+        5 => libc::CS5,
+        6 => libc::CS6,
+        7 => libc::CS7,
+        8 => libc::CS8,
+        _ => panic!("invalid data bits: {}", data_bits),
+    };
+}
+
+pub struct SerialPort {
+    file: File,
+    baud_rate: u32,
+    // bits per char, including start, parity and stop bits
+    char_bits: u32,
+}
+
+impl SerialPort {
+    pub fn open(
+        path: &str,
+        baud_rate: u32,
+        data_bits: u8,
+        parity: &str,
+        stop_bits: u8,
+    ) -> Result<Self, io::Error> {
+        let parity = parse_parity(parity);
+        // don't wait for the carrier
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(path)?;
+        let fd = file.as_raw_fd();
+        unsafe {
+            let mut tty: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut tty) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut tty);
+            let speed = get_speed(baud_rate);
+            libc::cfsetispeed(&mut tty, speed);
+            libc::cfsetospeed(&mut tty, speed);
+            tty.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB);
+            tty.c_cflag |= libc::CLOCAL | libc::CREAD | get_char_size(data_bits);
+            match parity {
+                Parity::None => {}
+                Parity::Even => tty.c_cflag |= libc::PARENB,
+                Parity::Odd => tty.c_cflag |= libc::PARENB | libc::PARODD,
+            }
+            match stop_bits {
+                1 => {}
+                2 => tty.c_cflag |= libc::CSTOPB,
+                _ => panic!("invalid stop bits: {}", stop_bits),
+            }
+            tty.c_iflag &= !(libc::IXON | libc::IXOFF | libc::IXANY);
+            // reads return immediately, timeouts are handled with poll
+            tty.c_cc[libc::VMIN] = 0;
+            tty.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(fd, libc::TCSANOW, &tty) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        return Ok(Self {
+            file,
+            baud_rate,
+            char_bits: 1
+                + data_bits as u32
+                + match parity {
+                    Parity::None => 0,
+                    _ => 1,
+                }
+                + stop_bits as u32,
+        });
+    }
+
+    // Modbus RTU inter-frame delay (3.5 chars, fixed 1.75 ms for baud rates
+    // above 19200)
+    pub fn frame_delay(&self) -> Duration {
+        return match self.baud_rate {
+            b if b > 19200 => Duration::from_micros(1750),
+            b => Duration::from_secs_f64(3.5 * self.char_bits as f64 / b as f64),
+        };
+    }
+
+    // discard the data received but not read
+    pub fn clear_input(&self) {
+        unsafe {
+            libc::tcflush(self.file.as_raw_fd(), libc::TCIFLUSH);
+        }
+    }
+
+    pub fn write_all(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        self.file.write_all(buf)?;
+        unsafe {
+            if libc::tcdrain(self.file.as_raw_fd()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn read_exact(&mut self, buf: &mut [u8], deadline: Instant) -> Result<(), io::Error> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::from(io::ErrorKind::TimedOut));
+            }
+            let mut pfd = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let wait_ms = (deadline - now).as_millis() as libc::c_int + 1;
+            let rc = unsafe { libc::poll(&mut pfd, 1, wait_ms) };
+            if rc < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if rc > 0 {
+                let n = self.file.read(&mut buf[pos..])?;
+                // nothing to read after hang up, the port is gone
+                if n == 0 && pfd.revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+                pos += n;
+            }
+        }
+        return Ok(());
+    }
+}
+
+// pseudo terminal: master file and the slave device path
+#[cfg(test)]
+pub fn open_pty() -> (File, String) {
+    use std::ffi::CStr;
+    use std::os::unix::io::FromRawFd;
+    let mut master: libc::c_int = 0;
+    let mut slave: libc::c_int = 0;
+    unsafe {
+        assert_eq!(
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null()
+            ),
+            0
+        );
+        let path = CStr::from_ptr(libc::ttyname(slave))
+            .to_string_lossy()
+            .into_owned();
+        libc::close(slave);
+        return (File::from_raw_fd(master), path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_request_response() {
+        let (mut master, path) = open_pty();
+        let mut port = SerialPort::open(&path, 9600, 8, "N", 1).unwrap();
+        let slave = thread::spawn(move || {
+            let mut request = [0u8; 8];
+            master.read_exact(&mut request).unwrap();
+            assert_eq!(request, [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0a]);
+            master
+                .write_all(&[0x01, 0x03, 0x02, 0x00, 0x2a, 0x39, 0x9b])
+                .unwrap();
+            master
+        });
+        port.write_all(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0a])
+            .unwrap();
+        let mut response = [0u8; 7];
+        port.read_exact(&mut response, Instant::now() + Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(response, [0x01, 0x03, 0x02, 0x00, 0x2a, 0x39, 0x9b]);
+        let _master = slave.join().unwrap();
+        // the slave is alive but silent
+        let started = Instant::now();
+        let err = port
+            .read_exact(&mut response, started + Duration::from_millis(100))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_hang_up() {
+        let (master, path) = open_pty();
+        let mut port = SerialPort::open(&path, 9600, 8, "N", 1).unwrap();
+        drop(master);
+        let started = Instant::now();
+        let mut buf = [0u8; 1];
+        let err = port
+            .read_exact(&mut buf, started + Duration::from_secs(2))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
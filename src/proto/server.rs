@@ -0,0 +1,237 @@
+// Modbus/TCP server (gateway), exposes pulled values via the register map
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use pl::datatypes::{GenDataType, GenDataTypeParse};
+use pl::tools::eprint;
+
+use rmodbus::server::{
+    context::{ModbusContext, CONTEXT_SIZE},
+    ModbusFrame,
+};
+use rmodbus::{ModbusFrameBuf, ModbusProto, MODBUS_ERROR_ILLEGAL_FUNCTION};
+
+use crate::ppmodbus::{
+    encode_value, get_register_count, parse_coil_value, parse_register, Addressing, DataOrder,
+};
+
+fn get_default_unit() -> u8 {
+    1
+}
+
+fn get_default_max_clients() -> usize {
+    16
+}
+
+fn get_default_client_timeout() -> f64 {
+    60.0
+}
+
+fn get_default_type() -> String {
+    "word".to_owned()
+}
+
+fn get_default_order() -> Option<String> {
+    None
+}
+
+#[derive(Deserialize)]
+pub struct ServerConfig {
+    listen: String,
+    #[serde(default = "get_default_unit")]
+    unit: u8,
+    // extra connections are closed at once
+    #[serde(alias = "max-clients", default = "get_default_max_clients")]
+    max_clients: usize,
+    // seconds, idle clients are disconnected
+    #[serde(default = "get_default_client_timeout")]
+    timeout: f64,
+    map: Vec<ServerMapEntry>,
+}
+
+#[derive(Deserialize)]
+struct ServerMapEntry {
+    #[serde(alias = "set-id")]
+    set_id: String,
+    reg: String,
+    #[serde(default = "get_default_type")]
+    r#type: String,
+    #[serde(alias = "byte-order", default = "get_default_order")]
+    byte_order: Option<String>,
+    #[serde(alias = "word-order", default = "get_default_order")]
+    word_order: Option<String>,
+}
+
+struct MappedValue {
+    tp: char,
+    addr: u16,
+    data_type: GenDataType,
+    order: DataOrder,
+}
+
+fn parse_map_entry(entry: &ServerMapEntry) -> MappedValue {
+    let (tp, addr) = match parse_register(&entry.reg, Addressing::Zero) {
+        Ok((tp, addr)) => (tp, addr as usize),
+        Err(e) => panic!("{}", e),
+    };
+    let data_type = entry.r#type.parse_data_type();
+    let count = match tp {
+        'h' | 'i' => {
+            match data_type {
+                GenDataType::Str(None) => {
+                    panic!("string length not specified for {}", entry.set_id)
+                }
+                GenDataType::Str(Some(_)) | GenDataType::StrBytes(_) => {}
+                _ => {
+                    // check the type is supported by the encoder
+                    if encode_value("0", data_type, &DataOrder::default()).is_err() {
+                        panic!("{} can not be mapped to registers", data_type);
+                    }
+                }
+            }
+            get_register_count(data_type)
+        }
+        'c' | 'd' => 1,
+        _ => panic!("unknown register type: {}", tp),
+    };
+    if addr + count > CONTEXT_SIZE {
+        panic!(
+            "{} is out of the server context (max {} registers)",
+            entry.reg, CONTEXT_SIZE
+        );
+    }
+    return MappedValue {
+        tp,
+        addr: addr as u16,
+        data_type,
+        order: DataOrder::default().with(entry.byte_order.as_ref(), entry.word_order.as_ref()),
+    };
+}
+
+fn encode_registers(value: &str, m: &MappedValue) -> Result<Vec<u16>, String> {
+    return match encode_value(value, m.data_type, &m.order) {
+        Ok(v) => Ok(v),
+        // transformed values may be floats, round them for integer types
+        Err(e) => match value.parse::<f64>() {
+            Ok(v) => encode_value(&format!("{}", v.round() as i64), m.data_type, &m.order),
+            _ => Err(e),
+        },
+    };
+}
+
+fn encode_bit(value: &str) -> Result<bool, String> {
+    return match value.parse::<f64>() {
+        Ok(v) => Ok(v != 0.0),
+        Err(_) => parse_coil_value(value),
+    };
+}
+
+fn set_value(ctx: &mut ModbusContext, value: &str, m: &MappedValue) -> Result<(), String> {
+    let result = match m.tp {
+        'h' => ctx.set_holdings_bulk(m.addr, &encode_registers(value, m)?),
+        'i' => ctx.set_inputs_bulk(m.addr, &encode_registers(value, m)?),
+        'c' => ctx.set_coil(m.addr, encode_bit(value)?),
+        'd' => ctx.set_discrete(m.addr, encode_bit(value)?),
+        _ => unreachable!(),
+    };
+    return result.map_err(|e| format!("{:?}", e));
+}
+
+fn handle_client(
+    mut stream: TcpStream,
+    unit: u8,
+    ctx: Arc<RwLock<ModbusContext>>,
+) -> Result<(), std::io::Error> {
+    loop {
+        let mut buf: ModbusFrameBuf = [0; 256];
+        // MBAP header, then the rest of the frame
+        stream.read_exact(&mut buf[..6])?;
+        let len = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+        if !(2..=250).contains(&len) {
+            return Ok(());
+        }
+        stream.read_exact(&mut buf[6..6 + len])?;
+        let mut response = Vec::new();
+        let mut frame = ModbusFrame::new(unit, &buf, ModbusProto::TcpUdp, &mut response);
+        if frame.parse().is_err() {
+            return Ok(());
+        }
+        if frame.processing_required {
+            match frame.readonly {
+                true => {
+                    if frame.process_read(&ctx.read().unwrap()).is_err() {
+                        return Ok(());
+                    }
+                }
+                // the gateway is read-only
+                false => frame.error = MODBUS_ERROR_ILLEGAL_FUNCTION,
+            }
+        }
+        if frame.response_required {
+            if frame.finalize_response().is_err() {
+                return Ok(());
+            }
+            stream.write_all(&response)?;
+        }
+    }
+}
+
+pub fn start(config: ServerConfig, verbose: bool) {
+    let mut map: HashMap<String, Vec<MappedValue>> = HashMap::new();
+    for entry in &config.map {
+        map.entry(entry.set_id.clone())
+            .or_default()
+            .push(parse_map_entry(entry));
+    }
+    let ctx = Arc::new(RwLock::new(ModbusContext::new()));
+    let listener = TcpListener::bind(&config.listen).expect("unable to bind Modbus server");
+    if verbose {
+        eprint(format!("Modbus server listening at {}", config.listen));
+    }
+    let event_ctx = ctx.clone();
+    pl::add_event_listener(Box::new(move |id, value, quality| {
+        // the last valid value is kept for items without data
+        if quality.is_null() {
+            return;
+        }
+        if let Some(entries) = map.get(id) {
+            let mut ctx = event_ctx.write().unwrap();
+            for m in entries {
+                if let Err(e) = set_value(&mut ctx, value, m) {
+                    eprint(format!("unable to set {} for Modbus server: {}", id, e));
+                }
+            }
+        }
+    }));
+    let unit = config.unit;
+    let max_clients = config.max_clients;
+    let timeout = Duration::from_secs_f64(config.timeout);
+    let clients = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if clients.load(Ordering::SeqCst) >= max_clients {
+                if verbose {
+                    eprint("Modbus server: too many clients".to_owned());
+                }
+                continue;
+            }
+            if stream.set_read_timeout(Some(timeout)).is_err() {
+                continue;
+            }
+            clients.fetch_add(1, Ordering::SeqCst);
+            let ctx = ctx.clone();
+            let clients = clients.clone();
+            thread::spawn(move || {
+                let _ = handle_client(stream, unit, ctx);
+                clients.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+}